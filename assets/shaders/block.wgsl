@@ -0,0 +1,40 @@
+// Samples block textures from the atlas, repeating a tile across merged faces.
+//
+// `uv` holds the position across the face in blocks, and `uv_b` holds the
+// minimum corner of the face's tile within the atlas.
+
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+
+struct BlockAtlas {
+    tile_size: vec2<f32>,
+}
+
+@group(2) @binding(100) var<uniform> block_atlas: BlockAtlas;
+@group(2) @binding(101) var atlas_texture: texture_2d<f32>;
+@group(2) @binding(102) var atlas_sampler: sampler;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    let uv = in.uv_b + fract(in.uv) * block_atlas.tile_size;
+    let ddx = dpdx(in.uv) * block_atlas.tile_size;
+    let ddy = dpdy(in.uv) * block_atlas.tile_size;
+
+    pbr_input.material.base_color *= textureSampleGrad(atlas_texture, atlas_sampler, uv, ddx, ddy);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    var out: FragmentOutput;
+
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+
+    return out;
+}
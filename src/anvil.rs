@@ -0,0 +1,547 @@
+//! Imports Minecraft Java Edition worlds from their Anvil `.mca` region files.
+//!
+//! Each region file holds 32x32 chunk columns behind a table of 4KiB sectors, and each
+//! column is a compressed NBT compound holding 16³ sections of palette indices packed
+//! into longs. Sections become chunks at the same position, with vanilla blocks mapped
+//! through [`BlockRegistry::by_vanilla_name`]. Versions before 1.13 aren't supported.
+
+use crate::block::{BlockRegistry, PLACEHOLDER_BLOCK};
+use crate::nbt::{self, Compound, Tag};
+use crate::save::WorldSave;
+use crate::world::{BlockId, Chunk, CHUNK_VOLUME};
+use bevy::prelude::*;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+const SECTOR_SIZE: usize = 4096;
+const REGION_COLUMNS: usize = 32 * 32;
+
+const GZIP: u8 = 1;
+const ZLIB: u8 = 2;
+const UNCOMPRESSED: u8 = 3;
+/// Set on the compression type of columns too large for the region, stored in `.mcc` files.
+const EXTERNAL: u8 = 0x80;
+
+/// The first data version (20w17a) where packed entries no longer span two longs.
+const PADDED_DATA_VERSION: i64 = 2529;
+
+/// How many chunks are imported before being written to the save.
+const FLUSH_CHUNKS: usize = 2048;
+
+/// What an import did, including which blocks had to be replaced by the placeholder.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub regions: usize,
+    pub columns: usize,
+    pub chunks: usize,
+    /// Columns that couldn't be read.
+    pub failed: usize,
+    /// How many of each unknown vanilla block were replaced by the placeholder.
+    pub unknown: HashMap<String, usize>,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "imported {} chunks from {} columns in {} regions",
+            self.chunks, self.columns, self.regions
+        )?;
+
+        if self.failed > 0 {
+            write!(f, ", {} columns failed", self.failed)?;
+        }
+
+        if self.unknown.is_empty() {
+            return Ok(());
+        }
+
+        let mut unknown: Vec<_> = self.unknown.iter().collect();
+
+        unknown.sort_by(|(a_name, a_count), (b_name, b_count)| {
+            b_count.cmp(a_count).then(a_name.cmp(b_name))
+        });
+
+        write!(
+            f,
+            "\n{} unknown blocks became {PLACEHOLDER_BLOCK}:",
+            unknown.len()
+        )?;
+
+        for (name, count) in unknown {
+            write!(f, "\n  {name}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Imports every region of a vanilla world into `save`, replacing chunks that already exist.
+///
+/// `dir` is either the world directory or its `region` directory.
+pub fn import_world(
+    dir: &Path,
+    registry: &BlockRegistry,
+    save: &WorldSave,
+) -> io::Result<ImportSummary> {
+    let region_dir = dir.join("region");
+    let region_dir = if region_dir.is_dir() {
+        region_dir
+    } else {
+        dir.to_path_buf()
+    };
+
+    let mut importer = Importer::new(registry);
+    let mut chunks = Vec::new();
+
+    for entry in fs::read_dir(&region_dir)? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some("mca") {
+            continue;
+        }
+
+        let region = fs::read(&path)?;
+
+        importer.summary.regions += 1;
+
+        for index in 0..REGION_COLUMNS {
+            let result = read_column(&region, index).and_then(|column| match column {
+                Some(column) => importer.import_column(&column, &mut chunks),
+                None => Ok(()),
+            });
+
+            if let Err(error) = result {
+                warn!(
+                    "failed to import column {index} of {}: {error}",
+                    path.display()
+                );
+                importer.summary.failed += 1;
+            }
+
+            if chunks.len() >= FLUSH_CHUNKS {
+                flush(save, &mut chunks)?;
+            }
+        }
+    }
+
+    flush(save, &mut chunks)?;
+
+    Ok(importer.summary)
+}
+
+fn flush(save: &WorldSave, chunks: &mut Vec<(IVec3, Chunk)>) -> io::Result<()> {
+    save.save_chunks(chunks.iter().map(|(position, chunk)| (*position, chunk)))?;
+    chunks.clear();
+
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads and decompresses a column, or `None` if it hasn't been generated.
+fn read_column(region: &[u8], index: usize) -> io::Result<Option<Compound>> {
+    let Some(&[a, b, c, sectors]) = region.get(index * 4..index * 4 + 4) else {
+        return Err(invalid_data("region is missing its header"));
+    };
+
+    let offset = u32::from_be_bytes([0, a, b, c]) as usize * SECTOR_SIZE;
+
+    if offset == 0 || sectors == 0 {
+        return Ok(None);
+    }
+
+    let Some(&[l0, l1, l2, l3, compression]) = region.get(offset..offset + 5) else {
+        return Err(invalid_data("column starts past the end of the region"));
+    };
+
+    // The length includes the compression type.
+    let length = u32::from_be_bytes([l0, l1, l2, l3]) as usize;
+    let data = region
+        .get(offset + 5..offset + 4 + length.max(1))
+        .ok_or_else(|| invalid_data("column extends past the end of the region"))?;
+
+    let mut bytes = Vec::new();
+
+    match compression {
+        GZIP => {
+            GzDecoder::new(data).read_to_end(&mut bytes)?;
+        }
+        ZLIB => {
+            ZlibDecoder::new(data).read_to_end(&mut bytes)?;
+        }
+        UNCOMPRESSED => bytes.extend_from_slice(data),
+        compression if compression & EXTERNAL != 0 => {
+            return Err(invalid_data(
+                "columns in external .mcc files are not supported",
+            ));
+        }
+        _ => return Err(invalid_data("unsupported compression")),
+    }
+
+    nbt::read(&bytes).map(|(_, root)| Some(root))
+}
+
+fn get_int(compound: &Compound, name: &str) -> Option<i64> {
+    compound.get(name).and_then(Tag::as_i64)
+}
+
+struct Importer<'a> {
+    registry: &'a BlockRegistry,
+    placeholder: BlockId,
+    summary: ImportSummary,
+}
+
+impl<'a> Importer<'a> {
+    fn new(registry: &'a BlockRegistry) -> Self {
+        Self {
+            registry,
+            placeholder: registry.placeholder(),
+            summary: ImportSummary::default(),
+        }
+    }
+
+    /// Converts the sections of a column into chunks.
+    fn import_column(
+        &mut self,
+        root: &Compound,
+        chunks: &mut Vec<(IVec3, Chunk)>,
+    ) -> io::Result<()> {
+        let data_version = get_int(root, "DataVersion").unwrap_or(0);
+
+        // Before 1.18, everything was nested in a `Level` compound with different names.
+        let (column, sections) = match root.get("Level").and_then(Tag::as_compound) {
+            Some(level) => (level, level.get("Sections")),
+            None => (root, root.get("sections")),
+        };
+
+        let (Some(x), Some(z)) = (get_int(column, "xPos"), get_int(column, "zPos")) else {
+            return Err(invalid_data("column has no position"));
+        };
+
+        let sections = sections.and_then(Tag::as_list).unwrap_or_default();
+        let spanning = data_version < PADDED_DATA_VERSION;
+
+        for section in sections {
+            let Some(section) = section.as_compound() else {
+                continue;
+            };
+
+            let Some(y) = get_int(section, "Y") else {
+                continue;
+            };
+
+            let (palette, data) = match section.get("block_states").and_then(Tag::as_compound) {
+                Some(block_states) => (block_states.get("palette"), block_states.get("data")),
+                None => (section.get("Palette"), section.get("BlockStates")),
+            };
+
+            // Sections without a palette only hold lighting.
+            let Some(palette) = palette.and_then(Tag::as_list) else {
+                continue;
+            };
+
+            let data = data.and_then(Tag::as_long_array).unwrap_or_default();
+            let chunk = self.import_section(palette, data, spanning)?;
+
+            chunks.push((IVec3::new(x as i32, y as i32, z as i32), chunk));
+            self.summary.chunks += 1;
+        }
+
+        self.summary.columns += 1;
+
+        Ok(())
+    }
+
+    fn import_section(
+        &mut self,
+        palette: &[Tag],
+        data: &[i64],
+        spanning: bool,
+    ) -> io::Result<Chunk> {
+        if palette.is_empty() {
+            return Err(invalid_data("section has an empty palette"));
+        }
+
+        let names: Vec<_> = palette
+            .iter()
+            .map(|state| {
+                state
+                    .as_compound()
+                    .and_then(|state| state.get("Name"))
+                    .and_then(Tag::as_str)
+                    .unwrap_or("minecraft:air")
+            })
+            .collect();
+
+        let mapped: Vec<_> = names
+            .iter()
+            .map(|name| self.registry.by_vanilla_name(name))
+            .collect();
+
+        let indices = if palette.len() == 1 {
+            vec![0; CHUNK_VOLUME]
+        } else {
+            unpack(data, bits_per_block(palette.len()), spanning)
+                .ok_or_else(|| invalid_data("section has too little block data"))?
+        };
+
+        let mut unknown = vec![0; palette.len()];
+        let mut blocks = Vec::with_capacity(CHUNK_VOLUME);
+
+        for index in indices {
+            let block = match mapped.get(index) {
+                Some(Some(block)) => *block,
+                Some(None) => {
+                    unknown[index] += 1;
+                    self.placeholder
+                }
+                None => return Err(invalid_data("block refers past the end of the palette")),
+            };
+
+            blocks.push(block);
+        }
+
+        for (name, count) in names.iter().zip(unknown) {
+            if count > 0 {
+                *self.summary.unknown.entry(name.to_string()).or_default() += count;
+            }
+        }
+
+        // Sections are ordered by Y, then Z, then X, just like chunks.
+        Chunk::from_blocks(blocks).ok_or_else(|| invalid_data("section has the wrong size"))
+    }
+}
+
+/// How many bits each palette index takes for a palette of `len` blocks.
+fn bits_per_block(len: usize) -> u32 {
+    (usize::BITS - (len - 1).leading_zeros()).max(4)
+}
+
+/// Unpacks a section's palette indices from longs, where `spanning` means an index
+/// may continue into the next long instead of leaving the rest of the long unused.
+fn unpack(data: &[i64], bits: u32, spanning: bool) -> Option<Vec<usize>> {
+    let bits = bits as usize;
+    let mask = (1u64 << bits) - 1;
+
+    (0..CHUNK_VOLUME)
+        .map(|index| {
+            let (long, shift) = if spanning {
+                (index * bits / 64, index * bits % 64)
+            } else {
+                let per_long = 64 / bits;
+
+                (index / per_long, index % per_long * bits)
+            };
+
+            let mut value = *data.get(long)? as u64 >> shift;
+
+            if shift + bits > 64 {
+                value |= (*data.get(long + 1)? as u64) << (64 - shift);
+            }
+
+            Some((value & mask) as usize)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// Packs palette indices into longs, the inverse of [`unpack`].
+    fn pack(indices: &[usize], bits: u32, spanning: bool) -> Vec<i64> {
+        let bits = bits as usize;
+        let per_long = 64 / bits;
+        let len = if spanning {
+            (indices.len() * bits).div_ceil(64)
+        } else {
+            indices.len().div_ceil(per_long)
+        };
+        let mut data = vec![0u64; len];
+
+        for (index, &value) in indices.iter().enumerate() {
+            let (long, shift) = if spanning {
+                (index * bits / 64, index * bits % 64)
+            } else {
+                (index / per_long, index % per_long * bits)
+            };
+
+            data[long] |= (value as u64) << shift;
+
+            if shift + bits > 64 {
+                data[long + 1] |= value as u64 >> (64 - shift);
+            }
+        }
+
+        data.into_iter().map(|long| long as i64).collect()
+    }
+
+    #[test]
+    fn bits_per_block_has_a_minimum_of_four() {
+        assert_eq!(bits_per_block(2), 4);
+        assert_eq!(bits_per_block(16), 4);
+        assert_eq!(bits_per_block(17), 5);
+        assert_eq!(bits_per_block(4096), 12);
+    }
+
+    #[test]
+    fn unpacks_indices_spanning_two_longs() {
+        // With 5 bits, the 13th index starts 4 bits before the end of the first long.
+        let mut data = vec![0; (CHUNK_VOLUME * 5).div_ceil(64)];
+
+        data[0] = (0b1011u64 << 60) as i64;
+        data[1] = 0b1;
+
+        let indices = unpack(&data, 5, true).unwrap();
+
+        assert_eq!(indices[11], 0);
+        assert_eq!(indices[12], 0b11011);
+        assert_eq!(indices[13], 0);
+    }
+
+    #[test]
+    fn unpacks_padded_indices() {
+        // With 5 bits, 12 indices fit in a long and the last 4 bits are left unused.
+        let mut data = vec![0; CHUNK_VOLUME.div_ceil(12)];
+
+        data[0] = (0b1111u64 << 60) as i64;
+        data[1] = 0b11011;
+
+        let indices = unpack(&data, 5, false).unwrap();
+
+        assert_eq!(indices[11], 0);
+        assert_eq!(indices[12], 0b11011);
+        assert_eq!(indices[13], 0);
+    }
+
+    #[test]
+    fn unpack_round_trips() {
+        let indices: Vec<_> = (0..CHUNK_VOLUME).map(|index| index * 7 % 17).collect();
+
+        for spanning in [false, true] {
+            let data = pack(&indices, 5, spanning);
+
+            assert_eq!(unpack(&data, 5, spanning).unwrap(), indices);
+            assert_eq!(unpack(&data[..data.len() - 1], 5, spanning), None);
+        }
+    }
+
+    fn name(name: &str) -> Tag {
+        Tag::Compound(Compound::from([(
+            "Name".to_string(),
+            Tag::String(name.to_string()),
+        )]))
+    }
+
+    /// A 1.18 column at (1, -2) with a section of stone, an unknown block and air repeating,
+    /// and a section of only stone above it.
+    fn column() -> Compound {
+        let indices: Vec<_> = (0..CHUNK_VOLUME).map(|index| index % 3).collect();
+        let mixed = Compound::from([
+            ("Y".to_string(), Tag::Byte(0)),
+            (
+                "block_states".to_string(),
+                Tag::Compound(Compound::from([
+                    (
+                        "palette".to_string(),
+                        Tag::List(vec![
+                            name("minecraft:stone"),
+                            name("minecraft:mystery"),
+                            name("minecraft:air"),
+                        ]),
+                    ),
+                    ("data".to_string(), Tag::LongArray(pack(&indices, 4, false))),
+                ])),
+            ),
+        ]);
+        let stone = Compound::from([
+            ("Y".to_string(), Tag::Byte(1)),
+            (
+                "block_states".to_string(),
+                Tag::Compound(Compound::from([(
+                    "palette".to_string(),
+                    Tag::List(vec![name("minecraft:stone")]),
+                )])),
+            ),
+        ]);
+
+        Compound::from([
+            ("DataVersion".to_string(), Tag::Int(3700)),
+            ("xPos".to_string(), Tag::Int(1)),
+            ("zPos".to_string(), Tag::Int(-2)),
+            (
+                "sections".to_string(),
+                Tag::List(vec![Tag::Compound(mixed), Tag::Compound(stone)]),
+            ),
+        ])
+    }
+
+    /// A region holding only `column`, as its first column.
+    fn region(column: &Compound) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        encoder.write_all(&nbt::write("", column)).unwrap();
+
+        let data = encoder.finish().unwrap();
+        let sectors = (data.len() + 5).div_ceil(SECTOR_SIZE) as u8;
+
+        // The header takes the first two sectors.
+        let mut region = vec![0; 2 * SECTOR_SIZE];
+
+        region[..4].copy_from_slice(&[0, 0, 2, sectors]);
+        region.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+        region.push(ZLIB);
+        region.extend_from_slice(&data);
+        region.resize(region.len().next_multiple_of(SECTOR_SIZE), 0);
+
+        region
+    }
+
+    #[test]
+    fn imports_columns_from_a_region() {
+        let registry = registry(&["stone", PLACEHOLDER_BLOCK]);
+        let stone = registry.by_name("stone").unwrap();
+        let placeholder = registry.placeholder();
+        let region = region(&column());
+
+        assert!(read_column(&region, 1).unwrap().is_none());
+
+        let column = read_column(&region, 0).unwrap().unwrap();
+        let mut importer = Importer::new(&registry);
+        let mut chunks = Vec::new();
+
+        importer.import_column(&column, &mut chunks).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].0, IVec3::new(1, 0, -2));
+        assert_eq!(chunks[1].0, IVec3::new(1, 1, -2));
+
+        for (index, &block) in chunks[0].1.blocks().iter().enumerate() {
+            let expected = [stone, placeholder, BlockId::AIR][index % 3];
+
+            assert_eq!(block, expected, "block {index}");
+        }
+
+        assert!(chunks[1].1.blocks().iter().all(|&block| block == stone));
+
+        let summary = importer.summary;
+
+        assert_eq!(summary.columns, 1);
+        assert_eq!(summary.chunks, 2);
+        assert_eq!(
+            summary.unknown,
+            HashMap::from([("minecraft:mystery".to_string(), CHUNK_VOLUME / 3)])
+        );
+    }
+}
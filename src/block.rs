@@ -0,0 +1,442 @@
+use crate::breaking::ToolKind;
+use crate::mesh::{BlockFaces, TEXTURE_SIZE};
+use crate::world::{BlockId, ChunkMap};
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+pub struct BlockPlugin;
+
+impl Plugin for BlockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<BlockDef>()
+            .init_asset_loader::<BlockDefLoader>();
+    }
+}
+
+/// The definition of a block type, loaded from a `.block.ron` file.
+#[derive(Asset, Clone, Debug, Deserialize, TypePath)]
+pub struct BlockDef {
+    pub id: u16,
+    pub name: String,
+    pub textures: FaceTextures,
+    /// Whether entities collide with the block.
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Whether the faces of neighbouring blocks can be seen through the block.
+    #[serde(default)]
+    pub transparent: bool,
+    /// How long the block takes to break.
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    /// The kind of tool that breaks the block faster.
+    #[serde(default)]
+    pub tool: Option<ToolKind>,
+    #[serde(default)]
+    pub drop: BlockDrop,
+    #[serde(default)]
+    pub placement: Placement,
+    /// Whether using the block opens a 3x3 crafting grid.
+    #[serde(default)]
+    pub crafting_table: bool,
+    /// Minecraft Java Edition blocks imported as this block, besides `minecraft:<name>`.
+    #[serde(default)]
+    pub vanilla: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+/// The texture file of each face of a block.
+///
+/// More specific faces take priority, so that `(all: "stone.png", top: "grass_top.png")`
+/// only changes the top face.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FaceTextures {
+    pub all: Option<String>,
+    pub side: Option<String>,
+    pub front: Option<String>,
+    pub back: Option<String>,
+    pub right: Option<String>,
+    pub left: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+}
+
+impl FaceTextures {
+    /// The texture of each face, in the order front, back, right, left, top, bottom.
+    pub fn resolve(&self) -> [Option<&str>; 6] {
+        let all = self.all.as_deref();
+        let side = self.side.as_deref().or(all);
+
+        [
+            self.front.as_deref().or(side),
+            self.back.as_deref().or(side),
+            self.right.as_deref().or(side),
+            self.left.as_deref().or(side),
+            self.top.as_deref().or(all),
+            self.bottom.as_deref().or(all),
+        ]
+    }
+}
+
+/// What a block drops when broken.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum BlockDrop {
+    #[default]
+    Itself,
+    Nothing,
+    Other(String),
+}
+
+/// Where players can place a block.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum Placement {
+    /// Against any face of another block.
+    #[default]
+    Anywhere,
+    /// Only on top of a solid block.
+    OnGround,
+    /// Only generated or imported, never placed by players.
+    Never,
+}
+
+#[derive(Default)]
+struct BlockDefLoader;
+
+#[derive(Debug)]
+pub enum BlockDefLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for BlockDefLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read block definition: {error}"),
+            Self::Ron(error) => write!(f, "failed to parse block definition: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BlockDefLoaderError {}
+
+impl AssetLoader for BlockDefLoader {
+    type Asset = BlockDef;
+    type Settings = ();
+    type Error = BlockDefLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(BlockDefLoaderError::Io)?;
+
+        ron::de::from_bytes(&bytes).map_err(BlockDefLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["block.ron"]
+    }
+}
+
+/// Generates the texture used in place of textures that failed to load,
+/// a magenta and black checkerboard.
+pub fn missing_texture() -> Image {
+    const SIZE: u32 = TEXTURE_SIZE as u32;
+
+    let data = (0..SIZE * SIZE)
+        .flat_map(|index| {
+            let (x, y) = (index % SIZE, index / SIZE);
+
+            if (x < SIZE / 2) == (y < SIZE / 2) {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect();
+
+    Image::new(
+        Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// The block that blocks from other games are imported as when they have no equivalent.
+pub const PLACEHOLDER_BLOCK: &str = "unknown";
+
+/// A registered block type.
+#[derive(Clone, Debug)]
+pub struct BlockType {
+    pub name: String,
+    /// The Minecraft Java Edition block this block is exported as.
+    pub vanilla: String,
+    /// The atlas index of each face, in the order front, back, right, left, top, bottom.
+    pub textures: [u32; 6],
+    pub solid: bool,
+    pub transparent: bool,
+    pub hardness: f32,
+    pub tool: Option<ToolKind>,
+    pub drop: Option<BlockId>,
+    pub placement: Placement,
+    pub crafting_table: bool,
+}
+
+/// Every block type, indexed by [`BlockId`].
+#[derive(Clone, Debug, Resource)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockType>>,
+    names: HashMap<String, BlockId>,
+    /// Blocks by the names of the vanilla blocks they're imported from.
+    vanilla: HashMap<String, BlockId>,
+    /// The minimum corner of each atlas texture, in UV coordinates.
+    texture_uvs: Vec<Vec2>,
+    /// The atlas index of the texture used for missing textures and unknown blocks.
+    missing_texture: u32,
+}
+
+impl BlockRegistry {
+    /// Builds the registry from block definitions.
+    ///
+    /// `texture_map` maps texture file names to atlas indices, textures that are
+    /// not in the atlas use `missing_texture` instead.
+    pub fn new(
+        defs: impl IntoIterator<Item = BlockDef>,
+        texture_map: &HashMap<String, u32>,
+        texture_uvs: Vec<Vec2>,
+        missing_texture: u32,
+    ) -> Self {
+        let mut defs: Vec<_> = defs.into_iter().collect();
+
+        defs.sort_by_key(|def| def.id);
+
+        let mut registry = Self {
+            blocks: Vec::new(),
+            names: HashMap::new(),
+            vanilla: HashMap::new(),
+            texture_uvs,
+            missing_texture,
+        };
+
+        registry.insert(
+            BlockId::AIR,
+            BlockType {
+                name: "air".into(),
+                vanilla: "minecraft:air".into(),
+                textures: [missing_texture; 6],
+                solid: false,
+                transparent: true,
+                hardness: 0.0,
+                tool: None,
+                drop: None,
+                placement: Placement::Never,
+                crafting_table: false,
+            },
+        );
+
+        for name in ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"] {
+            registry.vanilla.insert(name.into(), BlockId::AIR);
+        }
+
+        for def in &defs {
+            let id = BlockId(def.id);
+
+            if registry.get(id).is_some() {
+                warn!("block {} reuses id {}, skipping", def.name, def.id);
+                continue;
+            }
+
+            let textures = def.textures.resolve().map(|texture| {
+                let index = texture.and_then(|texture| texture_map.get(texture));
+
+                if index.is_none() {
+                    warn!("block {} is missing texture {texture:?}", def.name);
+                }
+
+                index.copied().unwrap_or(missing_texture)
+            });
+
+            let vanilla = def
+                .vanilla
+                .first()
+                .cloned()
+                .unwrap_or_else(|| format!("minecraft:{}", def.name));
+
+            registry.insert(
+                id,
+                BlockType {
+                    name: def.name.clone(),
+                    vanilla,
+                    textures,
+                    solid: def.solid,
+                    transparent: def.transparent,
+                    hardness: def.hardness,
+                    tool: def.tool,
+                    drop: None,
+                    placement: def.placement,
+                    crafting_table: def.crafting_table,
+                },
+            );
+
+            let vanilla = std::iter::once(format!("minecraft:{}", def.name));
+
+            for name in vanilla.chain(def.vanilla.iter().cloned()) {
+                registry.vanilla.insert(name, id);
+            }
+        }
+
+        // Drops may refer to blocks defined after them, so resolve them last.
+        for def in &defs {
+            let id = BlockId(def.id);
+            let drop = match &def.drop {
+                BlockDrop::Itself => Some(id),
+                BlockDrop::Nothing => None,
+                BlockDrop::Other(name) => {
+                    let drop = registry.by_name(name);
+
+                    if drop.is_none() {
+                        warn!("block {} drops unknown block {name}", def.name);
+                    }
+
+                    drop
+                }
+            };
+
+            if let Some(Some(block)) = registry.blocks.get_mut(id.0 as usize) {
+                if block.name == def.name {
+                    block.drop = drop;
+                }
+            }
+        }
+
+        registry
+    }
+
+    fn insert(&mut self, id: BlockId, block: BlockType) {
+        let index = id.0 as usize;
+
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, None);
+        }
+
+        self.names.insert(block.name.clone(), id);
+        self.blocks[index] = Some(block);
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockType> {
+        self.blocks.get(id.0 as usize)?.as_ref()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockId> {
+        self.names.get(name).copied()
+    }
+
+    /// Looks up the block a vanilla block is imported as, such as `minecraft:grass_block`.
+    pub fn by_vanilla_name(&self, name: &str) -> Option<BlockId> {
+        self.vanilla.get(name).copied()
+    }
+
+    /// The block that unknown blocks are imported as, or air if it isn't registered.
+    pub fn placeholder(&self) -> BlockId {
+        self.by_name(PLACEHOLDER_BLOCK).unwrap_or(BlockId::AIR)
+    }
+
+    /// Iterates over every registered block except air.
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockType)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(index, block)| Some((BlockId(index as u16), block.as_ref()?)))
+    }
+
+    pub fn is_solid(&self, id: BlockId) -> bool {
+        self.get(id).is_some_and(|block| block.solid)
+    }
+
+    /// Whether a player can place `block` at `position`, which must be empty.
+    pub fn can_place(&self, block: BlockId, position: IVec3, chunk_map: &ChunkMap) -> bool {
+        let Some(block_type) = self.get(block) else {
+            return false;
+        };
+
+        if !chunk_map.get_block(position).is_air() {
+            return false;
+        }
+
+        match block_type.placement {
+            Placement::Anywhere => true,
+            Placement::OnGround => self.is_solid(chunk_map.get_block(position - IVec3::Y)),
+            Placement::Never => false,
+        }
+    }
+}
+
+impl BlockFaces for BlockRegistry {
+    fn textures(&self, block: BlockId) -> [u32; 6] {
+        self.get(block)
+            .map_or([self.missing_texture; 6], |block| block.textures)
+    }
+
+    fn is_transparent(&self, block: BlockId) -> bool {
+        self.get(block).is_some_and(|block| block.transparent)
+    }
+
+    fn texture_uv(&self, texture: u32) -> Vec2 {
+        self.texture_uvs
+            .get(texture as usize)
+            .copied()
+            .unwrap_or(Vec2::ZERO)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A registry of solid, untextured blocks with ids counting up from 1 in the order of
+    /// `names`.
+    pub fn registry(names: &[&str]) -> BlockRegistry {
+        let defs = names.iter().enumerate().map(|(index, name)| BlockDef {
+            id: index as u16 + 1,
+            name: name.to_string(),
+            textures: FaceTextures::default(),
+            solid: true,
+            transparent: false,
+            hardness: 1.0,
+            tool: None,
+            drop: BlockDrop::Itself,
+            placement: Placement::Anywhere,
+            crafting_table: false,
+            vanilla: Vec::new(),
+        });
+
+        BlockRegistry::new(defs, &HashMap::new(), vec![Vec2::ZERO], 0)
+    }
+}
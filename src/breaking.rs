@@ -0,0 +1,264 @@
+//! Breaking blocks over time while `Action::Attack` is held, depending on their hardness and
+//! the held tool, with a crack overlay showing the progress.
+
+use crate::block::{BlockRegistry, BlockType};
+use crate::inventory::Inventory;
+use crate::inventory_screen::InventoryScreen;
+use crate::item::ItemRegistry;
+use crate::mesh::TEXTURE_SIZE;
+use crate::raycast::{update_target_block, TargetBlock};
+use crate::world::{BlockId, ChunkMap};
+use crate::{Action, GameState, PlayerBody};
+use bevy::image::ImageSampler;
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
+
+/// The number of crack textures shown while breaking a block.
+const CRACK_STAGES: usize = 10;
+
+pub struct BreakingPlugin;
+
+impl Plugin for BreakingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BlockBroken>()
+            .init_resource::<Breaking>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Setup,
+                    entered: GameState::InGame,
+                },
+                spawn_crack_overlay,
+            )
+            .add_systems(
+                Update,
+                (break_blocks, update_crack_overlay)
+                    .chain()
+                    .after(update_target_block)
+                    .run_if(in_state(InventoryScreen::Closed)),
+            );
+    }
+}
+
+/// The kinds of tools that break some blocks faster.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum ToolKind {
+    Pickaxe,
+    Axe,
+    Shovel,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct Tool {
+    pub kind: ToolKind,
+    /// How many times faster than by hand the tool breaks the blocks it's made for.
+    pub speed: f32,
+}
+
+/// Sent when a block finishes breaking.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct BlockBroken {
+    pub position: IVec3,
+    /// The block that was broken.
+    pub block: BlockId,
+}
+
+/// The block being broken, if any.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct Breaking {
+    pub block: Option<IVec3>,
+    /// How far along breaking the block is, from 0 to 1.
+    pub progress: f32,
+}
+
+#[derive(Component)]
+struct CrackOverlay;
+
+/// The overlay material of each crack stage.
+#[derive(Resource)]
+struct CrackMaterials(Vec<Handle<StandardMaterial>>);
+
+/// How long breaking a block takes, in seconds.
+pub fn break_time(block: &BlockType, tool: Option<Tool>) -> f32 {
+    let speed = tool
+        .filter(|tool| block.tool == Some(tool.kind))
+        .map_or(1.0, |tool| tool.speed);
+
+    block.hardness * 1.5 / speed
+}
+
+fn break_blocks(
+    time: Res<Time>,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut breaking: ResMut<Breaking>,
+    mut broken: EventWriter<BlockBroken>,
+    registry: Res<BlockRegistry>,
+    items: Res<ItemRegistry>,
+    target: Res<TargetBlock>,
+    player: Single<(&ActionState<Action>, &Inventory), With<PlayerBody>>,
+) {
+    let (action_state, inventory) = *player;
+
+    let Some(hit) = target.filter(|_| action_state.pressed(&Action::Attack)) else {
+        if breaking.block.is_some() {
+            *breaking = Breaking::default();
+        }
+
+        return;
+    };
+
+    // Looking at another block starts over.
+    if breaking.block != Some(hit.block) {
+        *breaking = Breaking {
+            block: Some(hit.block),
+            progress: 0.0,
+        };
+    }
+
+    let tool = inventory
+        .selected_stack()
+        .and_then(|stack| items.get(stack.item))
+        .and_then(|item| item.tool);
+
+    let block = chunk_map.get_block(hit.block);
+    let duration = registry
+        .get(block)
+        .map_or(0.0, |block_type| break_time(block_type, tool));
+
+    if duration > 0.0 {
+        breaking.progress += time.delta_secs() / duration;
+    } else {
+        breaking.progress = 1.0;
+    }
+
+    if breaking.progress >= 1.0 {
+        chunk_map.set_block(hit.block, BlockId::AIR);
+        broken.send(BlockBroken {
+            position: hit.block,
+            block,
+        });
+
+        *breaking = Breaking::default();
+    }
+}
+
+/// Generates the crack texture of a breaking stage, lines spreading out from the middle that
+/// grow longer with each stage.
+fn crack_texture(stage: usize) -> Image {
+    const SIZE: i32 = TEXTURE_SIZE as i32;
+    const DIRECTIONS: [IVec2; 6] = [
+        IVec2::new(1, 0),
+        IVec2::new(1, 1),
+        IVec2::new(-1, 1),
+        IVec2::new(-1, 0),
+        IVec2::new(-1, -1),
+        IVec2::new(1, -1),
+    ];
+
+    let mut data = vec![0; (SIZE * SIZE * 4) as usize];
+    let length = (stage as i32 + 1) * SIZE / CRACK_STAGES as i32;
+
+    for (index, direction) in DIRECTIONS.into_iter().enumerate() {
+        // Every stage walks the same lines, so the cracks grow rather than move around.
+        let mut seed = 0x9e37_79b9_u32.wrapping_mul(index as u32 + 1);
+        let mut pixel = IVec2::splat(SIZE / 2);
+
+        for _ in 0..length {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+
+            pixel += match seed % 4 {
+                0 => direction + direction.perp(),
+                1 => direction - direction.perp(),
+                _ => direction,
+            }
+            .clamp(IVec2::NEG_ONE, IVec2::ONE);
+
+            if pixel.cmplt(IVec2::ZERO).any() || pixel.cmpge(IVec2::splat(SIZE)).any() {
+                break;
+            }
+
+            let offset = ((pixel.y * SIZE + pixel.x) * 4) as usize;
+
+            data[offset..offset + 4].copy_from_slice(&[0, 0, 0, 180]);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: SIZE as u32,
+            height: SIZE as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+
+    image.sampler = ImageSampler::nearest();
+    image
+}
+
+fn spawn_crack_overlay(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let stages: Vec<_> = (0..CRACK_STAGES)
+        .map(|stage| {
+            materials.add(StandardMaterial {
+                base_color_texture: Some(images.add(crack_texture(stage))),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })
+        })
+        .collect();
+
+    commands.spawn((
+        CrackOverlay,
+        Mesh3d(meshes.add(Cuboid::from_size(Vec3::splat(1.002)))),
+        MeshMaterial3d(stages[0].clone()),
+        NotShadowCaster,
+        Transform::default(),
+        Visibility::Hidden,
+    ));
+
+    commands.insert_resource(CrackMaterials(stages));
+}
+
+/// Moves the crack overlay onto the block being broken and shows its breaking stage.
+fn update_crack_overlay(
+    breaking: Res<Breaking>,
+    stages: Res<CrackMaterials>,
+    overlay: Single<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut MeshMaterial3d<StandardMaterial>,
+        ),
+        With<CrackOverlay>,
+    >,
+) {
+    let (mut transform, mut visibility, mut material) = overlay.into_inner();
+
+    let Some(block) = breaking.block.filter(|_| breaking.progress > 0.0) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let stage = ((breaking.progress * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1);
+
+    transform.translation = block.as_vec3();
+    *visibility = Visibility::Visible;
+
+    if material.0 != stages.0[stage] {
+        material.0 = stages.0[stage].clone();
+    }
+}
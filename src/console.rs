@@ -0,0 +1,54 @@
+//! Commands typed into the terminal the game was started from, one per line, such as
+//! `time set noon`. Each line is sent as a [`ConsoleCommand`] for the plugin it concerns.
+
+use bevy::prelude::*;
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+
+        // Reading blocks until a line is entered, so it has a thread of its own.
+        thread::spawn(move || {
+            for line in io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        app.add_event::<ConsoleCommand>()
+            .insert_resource(Console(Mutex::new(receiver)))
+            .add_systems(PreUpdate, read_console);
+    }
+}
+
+/// A line entered into the console, without surrounding whitespace.
+#[derive(Clone, Debug, Deref, Event)]
+pub struct ConsoleCommand(pub String);
+
+#[derive(Resource)]
+struct Console(Mutex<Receiver<String>>);
+
+fn read_console(console: Res<Console>, mut commands: EventWriter<ConsoleCommand>) {
+    let Ok(receiver) = console.0.lock() else {
+        return;
+    };
+
+    for line in receiver.try_iter() {
+        let line = line.trim();
+
+        if !line.is_empty() {
+            commands.send(ConsoleCommand(line.to_string()));
+        }
+    }
+}
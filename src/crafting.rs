@@ -0,0 +1,477 @@
+//! Crafting items in a grid, 2x2 in the inventory and 3x3 at a crafting table, by recipes
+//! loaded from `.recipe.ron` files.
+
+use crate::item::{ItemId, ItemRegistry, ItemStack};
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The width of the crafting grid in the inventory.
+pub const INVENTORY_GRID: usize = 2;
+
+/// The width of the crafting grid at a crafting table.
+pub const TABLE_GRID: usize = 3;
+
+pub struct CraftingPlugin;
+
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<RecipeDef>()
+            .init_asset_loader::<RecipeDefLoader>();
+    }
+}
+
+/// A recipe, loaded from a `.recipe.ron` file.
+#[derive(Asset, Clone, Debug, Deserialize, TypePath)]
+pub struct RecipeDef {
+    /// The name of the crafted item.
+    pub result: String,
+    #[serde(default = "default_count")]
+    pub count: u8,
+    pub ingredients: Ingredients,
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+/// The items a recipe is made from, by name.
+#[derive(Clone, Debug, Deserialize)]
+pub enum Ingredients {
+    /// Items laid out in rows, such as `(pattern: ["PP", "PP"], key: {'P': "oak_planks"})`.
+    /// Spaces are empty cells, and mirrored layouts are accepted too.
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, String>,
+    },
+    /// Items placed anywhere in the grid.
+    Shapeless(Vec<String>),
+}
+
+#[derive(Default)]
+struct RecipeDefLoader;
+
+#[derive(Debug)]
+pub enum RecipeDefLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for RecipeDefLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read recipe: {error}"),
+            Self::Ron(error) => write!(f, "failed to parse recipe: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RecipeDefLoaderError {}
+
+impl AssetLoader for RecipeDefLoader {
+    type Asset = RecipeDef;
+    type Settings = ();
+    type Error = RecipeDefLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RecipeDefLoaderError::Io)?;
+
+        ron::de::from_bytes(&bytes).map_err(RecipeDefLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["recipe.ron"]
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Shape {
+    /// Cells row by row, `width` wide.
+    Shaped {
+        width: usize,
+        cells: Vec<Option<ItemId>>,
+    },
+    Shapeless(Vec<ItemId>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recipe {
+    pub shape: Shape,
+    pub result: ItemStack,
+}
+
+impl Recipe {
+    /// Whether the items in a crafting grid `width` wide, given row by row, make this recipe.
+    pub fn matches(&self, grid: &[Option<ItemId>], width: usize) -> bool {
+        match &self.shape {
+            Shape::Shaped {
+                width: recipe_width,
+                cells,
+            } => {
+                let Some((min, max)) = bounds(grid, width) else {
+                    return false;
+                };
+
+                let recipe_height = cells.len() / recipe_width;
+
+                if max.0 - min.0 + 1 != *recipe_width || max.1 - min.1 + 1 != recipe_height {
+                    return false;
+                }
+
+                let matches = |mirrored: bool| {
+                    (0..recipe_height).all(|y| {
+                        (0..*recipe_width).all(|x| {
+                            let cell = if mirrored { recipe_width - 1 - x } else { x };
+
+                            cells[y * recipe_width + cell] == grid[(min.1 + y) * width + min.0 + x]
+                        })
+                    })
+                };
+
+                matches(false) || matches(true)
+            }
+            Shape::Shapeless(ingredients) => {
+                let mut items: Vec<_> = grid.iter().flatten().copied().collect();
+                let mut ingredients = ingredients.clone();
+
+                items.sort();
+                ingredients.sort();
+
+                items == ingredients
+            }
+        }
+    }
+}
+
+/// The smallest and largest column and row of a grid that aren't empty.
+fn bounds(grid: &[Option<ItemId>], width: usize) -> Option<((usize, usize), (usize, usize))> {
+    let mut cells = grid
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.is_some())
+        .map(|(index, _)| (index % width, index / width));
+
+    let first = cells.next()?;
+
+    Some(cells.fold((first, first), |(min, max), (x, y)| {
+        ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+    }))
+}
+
+/// Finds the first recipe made by the items in a crafting grid `width` wide, given row by row.
+pub fn find_recipe<'a>(
+    recipes: &'a [Recipe],
+    grid: &[Option<ItemId>],
+    width: usize,
+) -> Option<&'a Recipe> {
+    recipes.iter().find(|recipe| recipe.matches(grid, width))
+}
+
+/// Every recipe.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RecipeRegistry {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeRegistry {
+    /// Builds the registry from recipe definitions, skipping recipes with unknown items and
+    /// shapeless recipes without any.
+    pub fn new(defs: impl IntoIterator<Item = RecipeDef>, items: &ItemRegistry) -> Self {
+        let item = |name: &str| {
+            let item = items.by_name(name);
+
+            if item.is_none() {
+                warn!("recipe uses unknown item {name}");
+            }
+
+            item
+        };
+
+        let mut defs: Vec<_> = defs.into_iter().collect();
+
+        // Shaped recipes take priority, and the rest keep the same order between runs.
+        defs.sort_by_key(|def| {
+            (
+                matches!(def.ingredients, Ingredients::Shapeless(_)),
+                def.result.clone(),
+            )
+        });
+
+        let recipes = defs
+            .into_iter()
+            .filter_map(|def| {
+                let shape = match &def.ingredients {
+                    Ingredients::Shaped { pattern, key } => {
+                        let width = pattern.iter().map(|row| row.chars().count()).max()?;
+                        let mut cells = Vec::new();
+
+                        for row in pattern {
+                            let mut row: Vec<_> = row
+                                .chars()
+                                .map(|cell| match cell {
+                                    ' ' => Some(None),
+                                    cell => key.get(&cell).and_then(|name| item(name)).map(Some),
+                                })
+                                .collect::<Option<_>>()?;
+
+                            row.resize(width, None);
+                            cells.extend(row);
+                        }
+
+                        // Leave out empty rows and columns around the pattern.
+                        let (min, max) = bounds(&cells, width)?;
+                        let cells = (min.1..=max.1)
+                            .flat_map(|y| (min.0..=max.0).map(move |x| (x, y)))
+                            .map(|(x, y)| cells[y * width + x])
+                            .collect();
+
+                        Shape::Shaped {
+                            width: max.0 - min.0 + 1,
+                            cells,
+                        }
+                    }
+                    Ingredients::Shapeless(names) if names.is_empty() => {
+                        warn!("shapeless recipe for {} has no ingredients", def.result);
+                        return None;
+                    }
+                    Ingredients::Shapeless(names) => Shape::Shapeless(
+                        names.iter().map(|name| item(name)).collect::<Option<_>>()?,
+                    ),
+                };
+
+                let result = ItemStack::new(item(&def.result)?, def.count.max(1));
+
+                Some(Recipe { shape, result })
+            })
+            .collect();
+
+        Self { recipes }
+    }
+
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+}
+
+/// The items in the player's crafting grid.
+#[derive(Clone, Component, Debug)]
+pub struct CraftingGrid {
+    /// Cells row by row, only the first `width * width` are used.
+    pub slots: [Option<ItemStack>; TABLE_GRID * TABLE_GRID],
+    pub width: usize,
+}
+
+impl Default for CraftingGrid {
+    fn default() -> Self {
+        Self {
+            slots: [None; TABLE_GRID * TABLE_GRID],
+            width: INVENTORY_GRID,
+        }
+    }
+}
+
+impl CraftingGrid {
+    fn items(&self) -> Vec<Option<ItemId>> {
+        self.slots[..self.width * self.width]
+            .iter()
+            .map(|slot| slot.map(|stack| stack.item))
+            .collect()
+    }
+
+    /// The item the grid currently makes, if any.
+    pub fn output(&self, recipes: &RecipeRegistry) -> Option<ItemStack> {
+        find_recipe(recipes.recipes(), &self.items(), self.width).map(|recipe| recipe.result)
+    }
+
+    /// Uses up one of each item in the grid to craft its output.
+    pub fn craft(&mut self, recipes: &RecipeRegistry) -> Option<ItemStack> {
+        let output = self.output(recipes)?;
+
+        for slot in &mut self.slots {
+            if let Some(stack) = slot {
+                stack.count -= 1;
+
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+
+        Some(output)
+    }
+
+    /// Takes every item out of the grid.
+    pub fn take_all(&mut self) -> impl Iterator<Item = ItemStack> + '_ {
+        self.slots.iter_mut().filter_map(Option::take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+
+    fn items() -> ItemRegistry {
+        ItemRegistry::new(
+            &registry(&["planks", "log", "stone"]),
+            [],
+            &HashMap::new(),
+            0,
+        )
+    }
+
+    fn shaped(result: &str, pattern: &[&str]) -> RecipeDef {
+        RecipeDef {
+            result: result.to_string(),
+            count: 1,
+            ingredients: Ingredients::Shaped {
+                pattern: pattern.iter().map(|row| row.to_string()).collect(),
+                key: HashMap::from([
+                    ('P', "planks".to_string()),
+                    ('L', "log".to_string()),
+                    ('S', "stone".to_string()),
+                ]),
+            },
+        }
+    }
+
+    fn shapeless(result: &str, names: &[&str]) -> RecipeDef {
+        RecipeDef {
+            result: result.to_string(),
+            count: 1,
+            ingredients: Ingredients::Shapeless(
+                names.iter().map(|name| name.to_string()).collect(),
+            ),
+        }
+    }
+
+    /// Lays out a grid from rows of item names' first letters, spaces are empty cells.
+    fn grid(items: &ItemRegistry, rows: &[&str]) -> Vec<Option<ItemId>> {
+        rows.iter()
+            .flat_map(|row| row.chars())
+            .map(|cell| match cell {
+                ' ' => None,
+                'P' => items.by_name("planks"),
+                'L' => items.by_name("log"),
+                'S' => items.by_name("stone"),
+                cell => panic!("unknown cell {cell}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shaped_recipes_match_their_pattern() {
+        let items = items();
+        let recipes = RecipeRegistry::new([shaped("stone", &["P ", "PL"])], &items);
+        let recipe = &recipes.recipes()[0];
+
+        assert!(recipe.matches(&grid(&items, &["P ", "PL"]), 2));
+        assert!(!recipe.matches(&grid(&items, &["P ", "LP"]), 2));
+        assert!(!recipe.matches(&grid(&items, &["PP", "PL"]), 2));
+        assert!(!recipe.matches(&grid(&items, &["P ", "P "]), 2));
+    }
+
+    #[test]
+    fn shaped_recipes_match_mirrored() {
+        let items = items();
+        let recipes = RecipeRegistry::new([shaped("stone", &["P ", "PL"])], &items);
+        let recipe = &recipes.recipes()[0];
+
+        assert!(recipe.matches(&grid(&items, &[" P", "LP"]), 2));
+
+        // Only left to right, not upside down.
+        assert!(!recipe.matches(&grid(&items, &["PL", "P "]), 2));
+    }
+
+    #[test]
+    fn shaped_recipes_match_anywhere_in_the_grid() {
+        let items = items();
+        let recipes = RecipeRegistry::new([shaped("stone", &["  ", " P", " L"])], &items);
+        let recipe = &recipes.recipes()[0];
+
+        assert!(recipe.matches(&grid(&items, &["P ", "L "]), 2));
+        assert!(recipe.matches(&grid(&items, &[" P", " L"]), 2));
+        assert!(recipe.matches(&grid(&items, &["   ", "  P", "  L"]), 3));
+        assert!(recipe.matches(&grid(&items, &["P  ", "L  ", "   "]), 3));
+    }
+
+    #[test]
+    fn large_recipes_need_a_large_grid() {
+        let items = items();
+        let recipes = RecipeRegistry::new(
+            [shaped("log", &["PPP"]), shaped("stone", &["PP", "PP"])],
+            &items,
+        );
+
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &["PP", "  "]), 2),
+            None
+        );
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &["   ", "PPP", "   "]), 3)
+                .map(|recipe| recipe.result.item),
+            items.by_name("log")
+        );
+
+        // The 2x2 recipe works in both grids, but not spread out in the larger one.
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &["PP", "PP"]), 2)
+                .map(|recipe| recipe.result.item),
+            items.by_name("stone")
+        );
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &[" PP", " PP", "   "]), 3)
+                .map(|recipe| recipe.result.item),
+            items.by_name("stone")
+        );
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &["P P", "   ", "P P"]), 3),
+            None
+        );
+    }
+
+    #[test]
+    fn shapeless_recipes_match_in_any_order() {
+        let items = items();
+        let recipes =
+            RecipeRegistry::new([shapeless("stone", &["log", "planks", "planks"])], &items);
+        let recipe = &recipes.recipes()[0];
+
+        assert!(recipe.matches(&grid(&items, &["PL", "P "]), 2));
+        assert!(recipe.matches(&grid(&items, &["  P", "L  ", " P "]), 3));
+        assert!(!recipe.matches(&grid(&items, &["PL", "  "]), 2));
+        assert!(!recipe.matches(&grid(&items, &["PL", "PP"]), 2));
+    }
+
+    #[test]
+    fn invalid_recipes_are_skipped() {
+        let items = items();
+        let recipes = RecipeRegistry::new(
+            [
+                shapeless("stone", &[]),
+                shapeless("stone", &["diamond"]),
+                shaped("diamond", &["PP"]),
+            ],
+            &items,
+        );
+
+        assert!(recipes.recipes().is_empty());
+        assert_eq!(
+            find_recipe(recipes.recipes(), &grid(&items, &["  ", "  "]), 2),
+            None
+        );
+    }
+}
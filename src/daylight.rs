@@ -0,0 +1,242 @@
+//! The day and night cycle, the [`WorldTime`] moves the sun across the sky and changes the
+//! light and sky color through dawn, day, dusk and night. `Action::SkipTime` skips ahead to the
+//! next time of day, and the `time set <time>` console command sets it.
+
+use crate::console::ConsoleCommand;
+use crate::world::WorldTime;
+use crate::{Action, GameState, PlayerBody};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use std::f32::consts::TAU;
+
+/// How long a day and night last, in seconds of world time.
+const DAY_LENGTH: f64 = 1200.0;
+
+/// How much of a day a tick is, for setting the time like Minecraft's `/time set`.
+const TICKS_PER_DAY: f64 = 24000.0;
+
+/// The times of day `Action::SkipTime` skips to, as understood by [`parse_time_of_day`].
+const SKIP_TIMES: [&str; 4] = ["day", "noon", "sunset", "midnight"];
+
+const DAY_ILLUMINANCE: f32 = light_consts::lux::AMBIENT_DAYLIGHT;
+const NIGHT_ILLUMINANCE: f32 = 300.0;
+
+const DAY_AMBIENT: f32 = 80.0;
+const NIGHT_AMBIENT: f32 = 15.0;
+
+const DAY_LIGHT: Srgba = Srgba::WHITE;
+const DUSK_LIGHT: Srgba = Srgba::rgb(1.0, 0.6, 0.35);
+const MOON_LIGHT: Srgba = Srgba::rgb(0.6, 0.7, 1.0);
+
+const DAY_SKY: Srgba = Srgba::rgb(0.47, 0.65, 1.0);
+const DUSK_SKY: Srgba = Srgba::rgb(0.9, 0.5, 0.3);
+const NIGHT_SKY: Srgba = Srgba::rgb(0.01, 0.01, 0.04);
+
+pub struct DaylightPlugin;
+
+impl Plugin for DaylightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (skip_time, set_time)
+                .before(advance_world_time)
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(
+            Update,
+            advance_world_time.run_if(not(in_state(GameState::Setup))),
+        )
+        .add_systems(
+            Update,
+            update_daylight
+                .after(advance_world_time)
+                .run_if(resource_exists::<WorldTime>),
+        );
+    }
+}
+
+/// How many seconds of world time pass every second.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct TimeRate(pub f64);
+
+/// The light of the sun, or of the moon while the sun is down.
+#[derive(Component)]
+pub struct Sun;
+
+/// Parses a time of day as a fraction of the day from sunrise, either `day`, `noon`, `sunset`,
+/// `night`, `midnight` or a number of ticks like Minecraft's `/time set`.
+pub fn parse_time_of_day(text: &str) -> Option<f64> {
+    let ticks = match text {
+        "day" => 1000.0,
+        "noon" => 6000.0,
+        "sunset" => 12000.0,
+        "night" => 13000.0,
+        "midnight" => 18000.0,
+        ticks => ticks.parse().ok()?,
+    };
+
+    Some((ticks / TICKS_PER_DAY).rem_euclid(1.0))
+}
+
+/// Moves the world time forward to the next time it's `fraction` of a day past sunrise.
+pub fn with_time_of_day(time: f64, fraction: f64) -> f64 {
+    let day = (time / DAY_LENGTH).floor();
+    let time_of_day = day * DAY_LENGTH + fraction * DAY_LENGTH;
+
+    if time_of_day < time {
+        time_of_day + DAY_LENGTH
+    } else {
+        time_of_day
+    }
+}
+
+/// Moves the world time forward to the next of the [`SKIP_TIMES`] when `Action::SkipTime` is
+/// pressed.
+fn skip_time(
+    player: Single<&ActionState<Action>, With<PlayerBody>>,
+    mut world_time: ResMut<WorldTime>,
+) {
+    if !player.just_pressed(&Action::SkipTime) {
+        return;
+    }
+
+    let next = SKIP_TIMES
+        .into_iter()
+        .filter_map(|name| Some((name, parse_time_of_day(name)?)))
+        .map(|(name, fraction)| {
+            let time = with_time_of_day(world_time.0, fraction);
+
+            // Already being at a time of day skips to it on the next day.
+            if time > world_time.0 {
+                (name, time)
+            } else {
+                (name, time + DAY_LENGTH)
+            }
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    if let Some((name, time)) = next {
+        info!("skipped to {name}");
+        world_time.0 = time;
+    }
+}
+
+/// Sets the world time from `time set <time>` console commands, to the next time it's the time
+/// of day given in any form [`parse_time_of_day`] accepts.
+fn set_time(mut commands: EventReader<ConsoleCommand>, mut world_time: ResMut<WorldTime>) {
+    for command in commands.read() {
+        let mut words = command.split_whitespace();
+
+        if words.next() != Some("time") {
+            continue;
+        }
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("set"), Some(time), None) => match parse_time_of_day(time) {
+                Some(fraction) => {
+                    world_time.0 = with_time_of_day(world_time.0, fraction);
+                    info!("set the time to {time}");
+                }
+                None => warn!("unknown time {time}"),
+            },
+            _ => warn!("usage: time set <day|noon|sunset|night|midnight|ticks>"),
+        }
+    }
+}
+
+/// Advances the world time at the [`TimeRate`], including while the player is dead.
+fn advance_world_time(time: Res<Time>, rate: Res<TimeRate>, mut world_time: ResMut<WorldTime>) {
+    world_time.0 += time.delta_secs_f64() * rate.0;
+}
+
+/// Moves the sun for the time of day, from the east at sunrise to the west at sunset, and
+/// shines the moon from the opposite side at night.
+fn update_daylight(
+    world_time: Res<WorldTime>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient_light: ResMut<AmbientLight>,
+    sun: Single<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+) {
+    let (mut light, mut transform) = sun.into_inner();
+    let angle = (world_time.0 / DAY_LENGTH).fract() as f32 * TAU;
+    let to_sun = Vec3::new(angle.cos(), angle.sin(), 0.2).normalize();
+    let elevation = to_sun.y;
+
+    // How much it's day rather than night, and how close the sun is to the horizon. The light
+    // is dimmest right at the horizon, where it switches between the sun and the moon, while
+    // the sky starts brightening a little before sunrise.
+    let daylight = smoothstep(0.0, 0.25, elevation);
+    let sky_daylight = smoothstep(-0.15, 0.2, elevation);
+    let dusk = 1.0 - (elevation.abs() / 0.3).min(1.0);
+
+    let to_light = if elevation >= 0.0 { to_sun } else { -to_sun };
+    let light_color = MOON_LIGHT.mix(&DAY_LIGHT, daylight).mix(&DUSK_LIGHT, dusk);
+    let sky_color = NIGHT_SKY
+        .mix(&DAY_SKY, sky_daylight)
+        .mix(&DUSK_SKY, dusk * 0.6);
+
+    *transform = Transform::IDENTITY.looking_to(-to_light, Vec3::Y);
+    light.color = light_color.into();
+    light.illuminance = NIGHT_ILLUMINANCE.lerp(DAY_ILLUMINANCE, daylight);
+    ambient_light.brightness = NIGHT_AMBIENT.lerp(DAY_AMBIENT, daylight);
+    clear_color.0 = sky_color.into();
+}
+
+/// Eases from 0 at `start` to 1 at `end`.
+fn smoothstep(start: f32, end: f32, value: f32) -> f32 {
+    let t = ((value - start) / (end - start)).clamp(0.0, 1.0);
+
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_named_times_and_ticks() {
+        assert_eq!(parse_time_of_day("noon"), Some(0.25));
+        assert_eq!(parse_time_of_day("sunset"), Some(0.5));
+        assert_eq!(parse_time_of_day("midnight"), Some(0.75));
+        assert_eq!(parse_time_of_day("18000"), Some(0.75));
+        assert!((parse_time_of_day("day").unwrap() - 1.0 / 24.0).abs() < 1e-9);
+        assert_eq!(parse_time_of_day("dusk"), None);
+    }
+
+    #[test]
+    fn ticks_wrap_around_the_day() {
+        assert_eq!(parse_time_of_day("24000"), Some(0.0));
+        assert_eq!(parse_time_of_day("30000"), Some(0.25));
+        assert_eq!(parse_time_of_day("-6000"), Some(0.75));
+    }
+
+    #[test]
+    fn time_of_day_moves_forward() {
+        let noon = DAY_LENGTH * 0.25;
+
+        assert_eq!(with_time_of_day(0.0, 0.25), noon);
+        assert_eq!(with_time_of_day(noon, 0.25), noon);
+        assert_eq!(with_time_of_day(noon - 1.0, 0.25), noon);
+        assert_eq!(
+            with_time_of_day(DAY_LENGTH * 3.0, 0.25),
+            DAY_LENGTH * 3.0 + noon
+        );
+    }
+
+    #[test]
+    fn time_of_day_wraps_past_midnight() {
+        // Already past noon, so the next noon is tomorrow.
+        let noon = DAY_LENGTH * 0.25;
+
+        assert_eq!(with_time_of_day(noon + 1.0, 0.25), DAY_LENGTH + noon);
+
+        // From just before sunrise to sunrise the next day, across midnight.
+        let midnight = DAY_LENGTH * 0.75;
+
+        assert_eq!(with_time_of_day(midnight + 10.0, 0.0), DAY_LENGTH);
+        assert_eq!(
+            with_time_of_day(DAY_LENGTH * 2.0 + midnight, 0.5),
+            DAY_LENGTH * 3.5
+        );
+    }
+}
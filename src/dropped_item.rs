@@ -0,0 +1,244 @@
+//! Items dropped into the world, as small physics bodies that bob and spin until they're
+//! picked up by the player, merge with nearby stacks of the same item, or despawn.
+
+use crate::block::BlockRegistry;
+use crate::breaking::BlockBroken;
+use crate::inventory::Inventory;
+use crate::item::{ItemId, ItemRegistry, ItemStack};
+use crate::mesh;
+use crate::physics::{CharacterBox, GameLayer};
+use crate::streaming::ChunkMaterial;
+use crate::{GameState, PlayerBody};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// The width of a dropped item, in blocks.
+const ITEM_SIZE: f32 = 0.25;
+
+/// How long after being dropped an item can be picked up, in seconds.
+const PICKUP_DELAY: f32 = 0.5;
+
+/// How far around the player's box items are picked up, in blocks.
+const PICKUP_RANGE: Vec3 = Vec3::new(1.0, 0.5, 1.0);
+
+/// How close stacks of the same item have to be to merge, in blocks.
+const MERGE_DISTANCE: f32 = 0.75;
+
+/// How long a dropped item stays in the world, in seconds.
+const DESPAWN_TIME: f32 = 300.0;
+
+pub struct DroppedItemPlugin;
+
+impl Plugin for DroppedItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DropItem>()
+            .init_resource::<ItemMeshes>()
+            .add_systems(
+                Update,
+                (
+                    drop_broken_blocks,
+                    spawn_dropped_items,
+                    age_dropped_items,
+                    merge_dropped_items,
+                    pick_up_dropped_items.run_if(in_state(GameState::InGame)),
+                    animate_dropped_items,
+                )
+                    .chain()
+                    .run_if(not(in_state(GameState::Setup))),
+            );
+    }
+}
+
+/// Drops a stack into the world.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct DropItem {
+    pub stack: ItemStack,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+/// A stack lying in the world.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct DroppedItem {
+    pub stack: ItemStack,
+    /// How long ago the item was dropped, in seconds.
+    pub age: f32,
+}
+
+/// The model of a dropped item, a child of the [`DroppedItem`] so that it can bob and spin
+/// without moving the physics body.
+#[derive(Component)]
+struct DroppedItemModel;
+
+/// The mesh of each item dropped so far.
+#[derive(Default, Resource)]
+struct ItemMeshes(HashMap<ItemId, Handle<Mesh>>);
+
+/// Drops what broken blocks drop.
+fn drop_broken_blocks(
+    mut broken: EventReader<BlockBroken>,
+    mut drops: EventWriter<DropItem>,
+    registry: Res<BlockRegistry>,
+    items: Res<ItemRegistry>,
+) {
+    for event in broken.read() {
+        let Some(item) = registry
+            .get(event.block)
+            .and_then(|block| block.drop)
+            .and_then(|block| items.by_block(block))
+        else {
+            continue;
+        };
+
+        drops.send(DropItem {
+            stack: ItemStack::new(item, 1),
+            position: event.position.as_vec3(),
+            velocity: Vec3::Y * 2.0,
+        });
+    }
+}
+
+fn spawn_dropped_items(
+    mut commands: Commands,
+    mut drops: EventReader<DropItem>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut item_meshes: ResMut<ItemMeshes>,
+    material: Res<ChunkMaterial>,
+    registry: Res<BlockRegistry>,
+    items: Res<ItemRegistry>,
+) {
+    for drop in drops.read() {
+        let Some(item) = items.get(drop.stack.item) else {
+            continue;
+        };
+
+        let mesh = item_meshes.0.entry(drop.stack.item).or_insert_with(|| {
+            meshes.add(match item.block {
+                Some(block) => mesh::new_block(&*registry, block),
+                None => mesh::new_item(&*registry, item.icon),
+            })
+        });
+
+        // Blocks are small cubes, other items are flat.
+        let scale = match item.block {
+            Some(_) => Vec3::splat(ITEM_SIZE),
+            None => Vec3::new(ITEM_SIZE * 2.0, ITEM_SIZE * 2.0, ITEM_SIZE / 4.0),
+        };
+
+        commands
+            .spawn((
+                DroppedItem {
+                    stack: drop.stack,
+                    age: 0.0,
+                },
+                RigidBody::Dynamic,
+                Collider::cuboid(ITEM_SIZE, ITEM_SIZE, ITEM_SIZE),
+                CollisionLayers::new(GameLayer::Item, GameLayer::World),
+                LockedAxes::ROTATION_LOCKED,
+                LinearVelocity(drop.velocity),
+                Transform::from_translation(drop.position),
+                Visibility::default(),
+            ))
+            .with_child((
+                DroppedItemModel,
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.0.clone()),
+                Transform::from_scale(scale),
+            ));
+    }
+}
+
+/// Ages dropped items, despawning them once they've been around for too long.
+fn age_dropped_items(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dropped: Query<(Entity, &mut DroppedItem)>,
+) {
+    for (entity, mut item) in &mut dropped {
+        item.age += time.delta_secs();
+
+        if item.age >= DESPAWN_TIME {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Merges stacks of the same item lying close together, if they fit in one stack.
+fn merge_dropped_items(
+    mut commands: Commands,
+    items: Res<ItemRegistry>,
+    mut dropped: Query<(Entity, &mut DroppedItem, &Position)>,
+) {
+    let mut combinations = dropped.iter_combinations_mut();
+
+    while let Some([(_, mut a, a_position), (b_entity, mut b, b_position)]) =
+        combinations.fetch_next()
+    {
+        // Stacks that were already merged away have no items left.
+        if a.stack.item != b.stack.item || a.stack.count == 0 || b.stack.count == 0 {
+            continue;
+        }
+
+        if a_position.0.distance(b_position.0) > MERGE_DISTANCE
+            || a.stack.count.saturating_add(b.stack.count) > items.max_stack(a.stack.item)
+        {
+            continue;
+        }
+
+        a.stack.count += b.stack.count;
+        a.age = a.age.min(b.age);
+        b.stack.count = 0;
+
+        commands.entity(b_entity).despawn_recursive();
+    }
+}
+
+/// Moves dropped items near the player into their inventory, as much as fits.
+fn pick_up_dropped_items(
+    mut commands: Commands,
+    items: Res<ItemRegistry>,
+    player: Single<(&CharacterBox, &Transform, &mut Inventory), With<PlayerBody>>,
+    mut dropped: Query<(Entity, &mut DroppedItem, &Position)>,
+) {
+    let (character_box, transform, mut inventory) = player.into_inner();
+    let aabb = character_box.aabb(transform.translation);
+    let min = aabb.min - PICKUP_RANGE;
+    let max = aabb.max + PICKUP_RANGE;
+
+    for (entity, mut item, position) in &mut dropped {
+        if item.age < PICKUP_DELAY || position.cmplt(min).any() || position.cmpgt(max).any() {
+            continue;
+        }
+
+        // Try on a copy first, so that a full inventory isn't changed every frame.
+        let mut updated = inventory.clone();
+        let rest = updated.add(item.stack, &items);
+
+        if rest == Some(item.stack) {
+            continue;
+        }
+
+        *inventory = updated;
+
+        match rest {
+            Some(rest) => item.stack = rest,
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
+
+/// Bobs and spins the models of dropped items.
+fn animate_dropped_items(
+    dropped: Query<&DroppedItem>,
+    mut models: Query<(&Parent, &mut Transform), With<DroppedItemModel>>,
+) {
+    for (parent, mut transform) in &mut models {
+        let Ok(item) = dropped.get(parent.get()) else {
+            continue;
+        };
+
+        transform.translation.y = ((item.age * 2.0).sin() + 1.0) * 0.05;
+        transform.rotation = Quat::from_rotation_y(item.age);
+    }
+}
@@ -0,0 +1,390 @@
+//! The player's health, lost by falling too far or into the void, and the death screen shown
+//! until they respawn at the world spawn point.
+
+use crate::dropped_item::DropItem;
+use crate::inventory::{Inventory, HOTBAR_SLOTS, SLOT_SIZE};
+use crate::physics::{Landed, Sprinting, TickTranslation};
+use crate::save::LevelInfo;
+use crate::{GameState, PlayerBody};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use std::f32::consts::PI;
+
+/// How much health the player has when full, in half hearts.
+const MAX_HEALTH: f32 = 20.0;
+
+/// How far the player can fall without taking damage, in blocks.
+const SAFE_FALL_HEIGHT: f32 = 3.0;
+
+/// How far below zero the void starts, in blocks.
+const VOID_LEVEL: f32 = -256.0;
+
+/// How much damage the void deals per second.
+const VOID_DAMAGE: f32 = 8.0;
+
+/// The height of a status bar above the hotbar, in pixels.
+const STATUS_BAR_HEIGHT: f32 = 10.0;
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                exited: GameState::Setup,
+                entered: GameState::InGame,
+            },
+            spawn_status_bars,
+        )
+        .add_systems(
+            Update,
+            (fall_damage, void_damage, die, update_health_bar)
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(
+            OnEnter(GameState::Dead),
+            (drop_inventory, release_cursor, spawn_death_screen),
+        )
+        .add_systems(Update, respawn_button.run_if(in_state(GameState::Dead)))
+        .add_systems(OnExit(GameState::Dead), (despawn_death_screen, respawn));
+    }
+}
+
+/// Rules for how the game plays.
+#[derive(Clone, Debug, Resource)]
+pub struct GameRules {
+    /// Whether the player keeps their inventory when they die, rather than dropping it.
+    pub keep_inventory: bool,
+}
+
+/// How much damage an entity can take before dying, in half hearts.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: MAX_HEALTH,
+            max: MAX_HEALTH,
+        }
+    }
+}
+
+impl Health {
+    pub fn damage(&mut self, amount: f32) {
+        self.current = (self.current - amount).max(0.0);
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// The row of bars above the hotbar, showing the player's health and other stats.
+#[derive(Component)]
+pub struct StatusBars;
+
+/// The filled part of the health bar.
+#[derive(Component)]
+struct HealthBar;
+
+/// The root node of the death screen.
+#[derive(Component)]
+struct DeathScreen;
+
+/// The button on the death screen that respawns the player.
+#[derive(Component)]
+struct RespawnButton;
+
+/// Spawns a bar into the [`StatusBars`], `marker` goes on the filled part, whose width is the
+/// percentage shown.
+pub fn spawn_status_bar(builder: &mut ChildBuilder, marker: impl Bundle, color: Color) {
+    builder
+        .spawn((
+            Node {
+                width: Val::Percent(45.0),
+                height: Val::Px(STATUS_BAR_HEIGHT),
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BorderColor(Color::srgb(0.3, 0.3, 0.3)),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        ))
+        .with_child((
+            marker,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            BackgroundColor(color),
+        ));
+}
+
+pub fn spawn_status_bars(mut commands: Commands) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(SLOT_SIZE + 16.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_children(|builder| {
+            builder
+                .spawn((
+                    StatusBars,
+                    Node {
+                        width: Val::Px(SLOT_SIZE * HOTBAR_SLOTS as f32),
+                        justify_content: JustifyContent::SpaceBetween,
+                        ..default()
+                    },
+                ))
+                .with_children(|builder| {
+                    spawn_status_bar(builder, HealthBar, Color::srgb(0.8, 0.1, 0.1));
+                });
+        });
+}
+
+/// Damages characters that land after falling further than [`SAFE_FALL_HEIGHT`], one half
+/// heart for every block beyond it.
+fn fall_damage(mut landed: EventReader<Landed>, mut healths: Query<&mut Health>) {
+    for event in landed.read() {
+        let Ok(mut health) = healths.get_mut(event.entity) else {
+            continue;
+        };
+
+        let damage = (event.fall_height - SAFE_FALL_HEIGHT).ceil();
+
+        if damage > 0.0 {
+            health.damage(damage);
+        }
+    }
+}
+
+/// Damages everything that fell below [`VOID_LEVEL`].
+fn void_damage(time: Res<Time>, mut healths: Query<(&Transform, &mut Health)>) {
+    for (transform, mut health) in &mut healths {
+        if transform.translation.y < VOID_LEVEL {
+            health.damage(VOID_DAMAGE * time.delta_secs());
+        }
+    }
+}
+
+fn die(player: Single<&Health, With<PlayerBody>>, mut next_state: ResMut<NextState<GameState>>) {
+    if player.is_dead() {
+        next_state.set(GameState::Dead);
+    }
+}
+
+fn update_health_bar(
+    player: Single<&Health, (With<PlayerBody>, Changed<Health>)>,
+    mut bar: Single<&mut Node, With<HealthBar>>,
+) {
+    bar.width = Val::Percent(player.current / player.max * 100.0);
+}
+
+/// Scatters the player's items around where they died, unless [`GameRules::keep_inventory`].
+fn drop_inventory(
+    rules: Res<GameRules>,
+    mut drops: EventWriter<DropItem>,
+    player: Single<(&Transform, &mut Inventory), With<PlayerBody>>,
+) {
+    if rules.keep_inventory {
+        return;
+    }
+
+    let (transform, mut inventory) = player.into_inner();
+
+    for (index, stack) in inventory.slots.iter_mut().enumerate() {
+        let Some(stack) = stack.take() else {
+            continue;
+        };
+
+        // Spread the stacks out evenly in every direction.
+        let angle = index as f32 * PI * (3.0 - 5.0f32.sqrt());
+        let direction = Vec2::from_angle(angle);
+
+        drops.send(DropItem {
+            stack,
+            position: transform.translation,
+            velocity: Vec3::new(direction.x * 2.0, 4.0, direction.y * 2.0),
+        });
+    }
+}
+
+fn release_cursor(mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    window.cursor_options.grab_mode = CursorGrabMode::None;
+    window.cursor_options.visible = true;
+}
+
+fn spawn_death_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/RobotoMono-Regular.ttf");
+
+    commands
+        .spawn((
+            DeathScreen,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(24.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.5, 0.0, 0.0, 0.4)),
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                Text::new("You died!"),
+                TextColor(Color::WHITE),
+                TextFont {
+                    font: font.clone(),
+                    font_size: 48.0,
+                    ..default()
+                },
+            ));
+
+            builder
+                .spawn((
+                    RespawnButton,
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(24.0), Val::Px(8.0)),
+                        border: UiRect::all(Val::Px(2.0)),
+                        ..default()
+                    },
+                    BorderColor(Color::srgb(0.3, 0.3, 0.3)),
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                ))
+                .with_child((
+                    Text::new("Respawn"),
+                    TextColor(Color::WHITE),
+                    TextFont { font, ..default() },
+                ));
+        });
+}
+
+fn respawn_button(
+    button: Single<(&Interaction, &mut BorderColor), (With<RespawnButton>, Changed<Interaction>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let (interaction, mut border) = button.into_inner();
+
+    match interaction {
+        Interaction::Pressed => next_state.set(GameState::InGame),
+        Interaction::Hovered => border.0 = Color::WHITE,
+        Interaction::None => border.0 = Color::srgb(0.3, 0.3, 0.3),
+    }
+}
+
+fn despawn_death_screen(mut commands: Commands, screen: Single<Entity, With<DeathScreen>>) {
+    commands.entity(*screen).despawn_recursive();
+}
+
+/// Heals the player and moves them back to the world spawn point.
+fn respawn(
+    mut commands: Commands,
+    level: Res<LevelInfo>,
+    player: Single<
+        (
+            Entity,
+            &mut Health,
+            &mut Transform,
+            &mut TickTranslation,
+            &mut LinearVelocity,
+        ),
+        With<PlayerBody>,
+    >,
+) {
+    let (entity, mut health, mut transform, mut translation, mut linear_velocity) =
+        player.into_inner();
+    let spawn = level.spawn.as_vec3();
+
+    health.current = health.max;
+    transform.translation = spawn;
+    *translation = TickTranslation::new(spawn);
+    linear_velocity.0 = Vec3::ZERO;
+
+    commands.entity(entity).remove::<Sprinting>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use crate::physics::{CharacterControllerBundle, CharacterControllerPlugin};
+    use crate::world::{BlockId, ChunkMap};
+    use crate::PLAYER_SIZE;
+    use bevy::state::app::StatesPlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    /// The top of the floor the character lands on.
+    const FLOOR: f32 = -0.5;
+
+    /// Drops a character onto a floor from `height` blocks above it, returning the damage
+    /// it took.
+    fn drop_from(height: f32) -> f32 {
+        let mut app = App::new();
+        let mut chunk_map = ChunkMap::default();
+
+        for x in -2..2 {
+            for z in -2..2 {
+                chunk_map.set_block(IVec3::new(x, -1, z), BlockId(1));
+            }
+        }
+
+        app.add_plugins((MinimalPlugins, StatesPlugin, CharacterControllerPlugin))
+            .insert_state(GameState::InGame)
+            .insert_resource(chunk_map)
+            .insert_resource(registry(&["stone"]))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO))
+            .add_systems(Update, fall_damage);
+
+        let character = app
+            .world_mut()
+            .spawn((
+                Health::default(),
+                CharacterControllerBundle::new(PLAYER_SIZE, Vec3::NEG_Y * 9.81 * 2.0),
+                Transform::from_xyz(0.0, FLOOR + PLAYER_SIZE.y / 2.0 + height, 0.0),
+            ))
+            .id();
+
+        // The first update only starts the clock.
+        app.update();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(
+            Duration::from_secs(1) / 60,
+        ));
+
+        // Long enough to land from either height, and lie there for a while after.
+        for _ in 0..120 {
+            app.update();
+        }
+
+        let health = app.world().get::<Health>(character).unwrap();
+
+        health.max - health.current
+    }
+
+    #[test]
+    fn safe_fall_deals_no_damage() {
+        assert_eq!(drop_from(3.0), 0.0);
+    }
+
+    #[test]
+    fn fall_damage_grows_with_height() {
+        assert_eq!(drop_from(10.0), 7.0);
+    }
+}
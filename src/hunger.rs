@@ -0,0 +1,242 @@
+//! The player's hunger, drained by sprinting, jumping and breaking blocks, and refilled by
+//! holding `Action::Use` with food. A full stomach heals the player, an empty one hurts them.
+
+use crate::breaking::BlockBroken;
+use crate::health::{spawn_status_bar, spawn_status_bars, Health, StatusBars};
+use crate::inventory::Inventory;
+use crate::inventory_screen::InventoryScreen;
+use crate::item::ItemRegistry;
+use crate::physics::{Jumped, Sprinting};
+use crate::{Action, GameState, PlayerBody};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
+
+/// How much food the player has when full, in half drumsticks.
+const MAX_FOOD: f32 = 20.0;
+
+/// How much exhaustion uses up one point of saturation, or of food once saturation is gone.
+const EXHAUSTION_PER_POINT: f32 = 4.0;
+
+/// How much exhaustion sprinting adds per second.
+const SPRINT_EXHAUSTION: f32 = 0.5;
+
+/// How much exhaustion a jump adds, more while sprinting.
+const JUMP_EXHAUSTION: f32 = 0.05;
+const SPRINT_JUMP_EXHAUSTION: f32 = 0.2;
+
+/// How much exhaustion breaking a block adds.
+const BREAK_EXHAUSTION: f32 = 0.005;
+
+/// How much food the player needs to heal.
+const REGENERATION_FOOD: f32 = 18.0;
+
+/// How much exhaustion healing a half heart adds.
+const REGENERATION_EXHAUSTION: f32 = 6.0;
+
+/// How long between healing a half heart while fed, or losing one while starving, in seconds.
+const HUNGER_INTERVAL: f32 = 4.0;
+
+/// How long `Action::Use` has to be held to eat, in seconds.
+const EAT_TIME: f32 = 1.6;
+
+pub struct HungerPlugin;
+
+impl Plugin for HungerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                exited: GameState::Setup,
+                entered: GameState::InGame,
+            },
+            spawn_hunger_bar.after(spawn_status_bars),
+        )
+        .add_systems(
+            Update,
+            (
+                exhaust_sprinting,
+                exhaust_jumping,
+                exhaust_breaking,
+                eat.run_if(in_state(InventoryScreen::Closed)),
+                digest,
+                regenerate_or_starve,
+                update_hunger_bar,
+            )
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(OnExit(GameState::Dead), reset_hunger);
+    }
+}
+
+/// What eating an item restores.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct Food {
+    /// How much food the item restores, in half drumsticks.
+    pub hunger: u8,
+    /// How much saturation the item restores, never more than the player's food.
+    pub saturation: f32,
+}
+
+/// How fed an entity is. Exhaustion builds up from activity and uses up saturation first,
+/// then food.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct Hunger {
+    pub food: f32,
+    pub saturation: f32,
+    pub exhaustion: f32,
+    /// How long since the last half heart was healed or lost, in seconds.
+    timer: f32,
+}
+
+impl Default for Hunger {
+    fn default() -> Self {
+        Self {
+            food: MAX_FOOD,
+            saturation: 5.0,
+            exhaustion: 0.0,
+            timer: 0.0,
+        }
+    }
+}
+
+impl Hunger {
+    pub fn exhaust(&mut self, amount: f32) {
+        self.exhaustion += amount;
+    }
+
+    pub fn eat(&mut self, food: Food) {
+        self.food = (self.food + food.hunger as f32).min(MAX_FOOD);
+        self.saturation = (self.saturation + food.saturation).min(self.food);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.food >= MAX_FOOD
+    }
+}
+
+/// The filled part of the hunger bar.
+#[derive(Component)]
+struct HungerBar;
+
+fn spawn_hunger_bar(mut commands: Commands, bars: Single<Entity, With<StatusBars>>) {
+    commands.entity(*bars).with_children(|builder| {
+        spawn_status_bar(builder, HungerBar, Color::srgb(0.7, 0.45, 0.15));
+    });
+}
+
+fn exhaust_sprinting(time: Res<Time>, mut hungers: Query<&mut Hunger, With<Sprinting>>) {
+    for mut hunger in &mut hungers {
+        hunger.exhaust(SPRINT_EXHAUSTION * time.delta_secs());
+    }
+}
+
+fn exhaust_jumping(
+    mut jumped: EventReader<Jumped>,
+    mut hungers: Query<(&mut Hunger, Has<Sprinting>)>,
+) {
+    for event in jumped.read() {
+        let Ok((mut hunger, is_sprinting)) = hungers.get_mut(event.entity) else {
+            continue;
+        };
+
+        hunger.exhaust(if is_sprinting {
+            SPRINT_JUMP_EXHAUSTION
+        } else {
+            JUMP_EXHAUSTION
+        });
+    }
+}
+
+fn exhaust_breaking(
+    mut broken: EventReader<BlockBroken>,
+    mut hunger: Single<&mut Hunger, With<PlayerBody>>,
+) {
+    for _ in broken.read() {
+        hunger.exhaust(BREAK_EXHAUSTION);
+    }
+}
+
+/// Eats the selected item once `Action::Use` has been held for [`EAT_TIME`], unless the player
+/// is full.
+fn eat(
+    time: Res<Time>,
+    mut eating: Local<f32>,
+    items: Res<ItemRegistry>,
+    player: Single<(&ActionState<Action>, &mut Inventory, &mut Hunger), With<PlayerBody>>,
+) {
+    let (action_state, mut inventory, mut hunger) = player.into_inner();
+    let food = inventory
+        .selected_stack()
+        .and_then(|stack| items.get(stack.item))
+        .and_then(|item| item.food);
+
+    let Some(food) = food.filter(|_| action_state.pressed(&Action::Use) && !hunger.is_full())
+    else {
+        *eating = 0.0;
+        return;
+    };
+
+    *eating += time.delta_secs();
+
+    if *eating >= EAT_TIME {
+        *eating = 0.0;
+        inventory.take_selected(1);
+        hunger.eat(food);
+    }
+}
+
+/// Uses up saturation, then food, for the exhaustion built up.
+fn digest(mut hungers: Query<&mut Hunger>) {
+    for mut hunger in &mut hungers {
+        while hunger.exhaustion >= EXHAUSTION_PER_POINT {
+            hunger.exhaustion -= EXHAUSTION_PER_POINT;
+
+            if hunger.saturation > 0.0 {
+                hunger.saturation = (hunger.saturation - 1.0).max(0.0);
+            } else {
+                hunger.food = (hunger.food - 1.0).max(0.0);
+            }
+        }
+    }
+}
+
+/// Heals well fed entities and hurts starving ones, a half heart every [`HUNGER_INTERVAL`].
+fn regenerate_or_starve(time: Res<Time>, mut entities: Query<(&mut Hunger, &mut Health)>) {
+    for (mut hunger, mut health) in &mut entities {
+        let fed = hunger.food >= REGENERATION_FOOD && health.current < health.max;
+        let starving = hunger.food <= 0.0;
+
+        if !fed && !starving {
+            hunger.timer = 0.0;
+            continue;
+        }
+
+        hunger.timer += time.delta_secs();
+
+        if hunger.timer < HUNGER_INTERVAL {
+            continue;
+        }
+
+        hunger.timer = 0.0;
+
+        if fed {
+            health.heal(1.0);
+            hunger.exhaust(REGENERATION_EXHAUSTION);
+        } else {
+            health.damage(1.0);
+        }
+    }
+}
+
+fn update_hunger_bar(
+    player: Single<&Hunger, (With<PlayerBody>, Changed<Hunger>)>,
+    mut bar: Single<&mut Node, With<HungerBar>>,
+) {
+    bar.width = Val::Percent(player.food / MAX_FOOD * 100.0);
+}
+
+/// Fills the player back up when they respawn.
+fn reset_hunger(mut hunger: Single<&mut Hunger, With<PlayerBody>>) {
+    **hunger = Hunger::default();
+}
@@ -0,0 +1,207 @@
+//! Placing and picking the targeted block with `Action::Use` and `Action::Pick`, and using
+//! crafting tables. Breaking is in [`crate::breaking`].
+
+use crate::block::BlockRegistry;
+use crate::crafting::{CraftingGrid, TABLE_GRID};
+use crate::inventory::{Inventory, HOTBAR_SLOTS};
+use crate::inventory_screen::InventoryScreen;
+use crate::item::{ItemRegistry, ItemStack};
+use crate::physics::{overlaps_solid_block, CharacterBox};
+use crate::raycast::{update_target_block, TargetBlock};
+use crate::world::ChunkMap;
+use crate::{Action, PlayerBody};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (place_block, pick_block)
+                .chain()
+                .after(update_target_block)
+                .run_if(in_state(InventoryScreen::Closed)),
+        );
+    }
+}
+
+/// Opens the 3x3 crafting grid when using a crafting table, otherwise places the held block
+/// against the targeted face, unless it would end up inside the player.
+#[allow(clippy::too_many_arguments)]
+fn place_block(
+    mut chunk_map: ResMut<ChunkMap>,
+    mut next_screen: ResMut<NextState<InventoryScreen>>,
+    registry: Res<BlockRegistry>,
+    items: Res<ItemRegistry>,
+    target: Res<TargetBlock>,
+    player: Single<
+        (
+            &ActionState<Action>,
+            &mut Inventory,
+            &mut CraftingGrid,
+            &CharacterBox,
+            &Transform,
+        ),
+        With<PlayerBody>,
+    >,
+) {
+    let (action_state, mut inventory, mut grid, character_box, transform) = player.into_inner();
+
+    if !action_state.just_pressed(&Action::Use) {
+        return;
+    }
+
+    let Some(hit) = **target else {
+        return;
+    };
+
+    if registry
+        .get(chunk_map.get_block(hit.block))
+        .is_some_and(|block| block.crafting_table)
+    {
+        grid.width = TABLE_GRID;
+        next_screen.set(InventoryScreen::Open);
+        return;
+    }
+
+    let Some(block) = inventory
+        .selected_stack()
+        .and_then(|stack| items.get(stack.item))
+        .and_then(|item| item.block)
+    else {
+        return;
+    };
+
+    let placed = hit.block + hit.normal;
+    let player_box = character_box.aabb(transform.translation);
+
+    if registry.can_place(block, placed, &chunk_map)
+        && !overlaps_solid_block(player_box.min, player_box.max, |block| block == placed)
+    {
+        chunk_map.set_block(placed, block);
+        inventory.take_selected(1);
+    }
+}
+
+/// Selects the targeted block in the hotbar, or moves it there from storage. If the player
+/// doesn't have the block, a stack of it is copied into the selected slot.
+fn pick_block(
+    chunk_map: Res<ChunkMap>,
+    items: Res<ItemRegistry>,
+    target: Res<TargetBlock>,
+    player: Single<(&ActionState<Action>, &mut Inventory), With<PlayerBody>>,
+) {
+    let (action_state, mut inventory) = player.into_inner();
+
+    if !action_state.just_pressed(&Action::Pick) {
+        return;
+    }
+
+    let Some(item) = target.and_then(|hit| items.by_block(chunk_map.get_block(hit.block))) else {
+        return;
+    };
+
+    let selected = inventory.selected;
+
+    match inventory.find(item) {
+        Some(slot) if slot < HOTBAR_SLOTS => inventory.selected = slot,
+        Some(slot) => inventory.slots.swap(slot, selected),
+        None => inventory.slots[selected] = Some(ItemStack::new(item, items.max_stack(item))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use crate::raycast::RayHit;
+    use crate::world::BlockId;
+    use crate::PLAYER_SIZE;
+    use std::collections::HashMap;
+
+    /// Uses a stack of stone on `hit` while standing on the floor at the origin, returning the
+    /// world and the inventory afterwards.
+    fn use_stone(hit: RayHit) -> (ChunkMap, Inventory) {
+        let mut app = App::new();
+        let blocks = registry(&["stone"]);
+        let items = ItemRegistry::new(&blocks, [], &HashMap::new(), 0);
+        let stone = items.by_name("stone").unwrap();
+        let mut chunk_map = ChunkMap::default();
+
+        for x in -2..=2 {
+            for z in -2..=2 {
+                chunk_map.set_block(IVec3::new(x, -1, z), BlockId(1));
+            }
+        }
+
+        // A wall to place against.
+        chunk_map.set_block(IVec3::new(2, 0, 0), BlockId(1));
+
+        app.add_systems(Update, place_block)
+            .init_resource::<NextState<InventoryScreen>>()
+            .insert_resource(chunk_map)
+            .insert_resource(blocks)
+            .insert_resource(items)
+            .insert_resource(TargetBlock(Some(hit)));
+
+        let mut action_state = ActionState::<Action>::default();
+        let mut inventory = Inventory::default();
+
+        action_state.press(&Action::Use);
+        inventory.slots[0] = Some(ItemStack::new(stone, 64));
+
+        let player = app
+            .world_mut()
+            .spawn((
+                PlayerBody,
+                action_state,
+                inventory,
+                CraftingGrid::default(),
+                CharacterBox::new(PLAYER_SIZE),
+                Transform::from_xyz(0.0, PLAYER_SIZE.y / 2.0 - 0.5, 0.0),
+            ))
+            .id();
+
+        app.update();
+
+        let inventory = app
+            .world_mut()
+            .entity_mut(player)
+            .take::<Inventory>()
+            .unwrap();
+
+        (app.world_mut().remove_resource().unwrap(), inventory)
+    }
+
+    #[test]
+    fn refuses_blocks_overlapping_the_player() {
+        let (chunk_map, inventory) = use_stone(RayHit {
+            block: IVec3::new(0, -1, 0),
+            normal: IVec3::Y,
+            distance: 1.0,
+        });
+
+        assert!(chunk_map.get_block(IVec3::ZERO).is_air());
+        assert_eq!(
+            inventory.selected_stack().map(|stack| stack.count),
+            Some(64)
+        );
+    }
+
+    #[test]
+    fn places_against_side_faces() {
+        let (chunk_map, inventory) = use_stone(RayHit {
+            block: IVec3::new(2, 0, 0),
+            normal: IVec3::NEG_X,
+            distance: 1.0,
+        });
+
+        assert_eq!(chunk_map.get_block(IVec3::new(1, 0, 0)), BlockId(1));
+        assert_eq!(
+            inventory.selected_stack().map(|stack| stack.count),
+            Some(63)
+        );
+    }
+}
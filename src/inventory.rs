@@ -0,0 +1,550 @@
+//! The player's inventory, a hotbar of [`HOTBAR_SLOTS`] slots followed by storage, and the
+//! hotbar shown at the bottom of the screen.
+
+use crate::crafting::{CraftingGrid, RecipeRegistry};
+use crate::inventory_screen::InventoryScreen;
+use crate::item::{ItemId, ItemRegistry, ItemStack};
+use crate::{Action, GameState, PlayerBody, State};
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use std::ops::Range;
+
+/// The number of slots in the hotbar, selectable with `Action::Hotbar`.
+pub const HOTBAR_SLOTS: usize = 9;
+
+/// The number of slots in the whole inventory, including the hotbar.
+pub const INVENTORY_SLOTS: usize = HOTBAR_SLOTS + 27;
+
+/// The size of a slot in the inventory UI, in pixels.
+pub const SLOT_SIZE: f32 = 44.0;
+
+const SLOT_BORDER: Color = Color::srgb(0.3, 0.3, 0.3);
+const SELECTED_SLOT_BORDER: Color = Color::WHITE;
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                exited: GameState::Setup,
+                entered: GameState::InGame,
+            },
+            spawn_hotbar,
+        )
+        .add_systems(
+            Update,
+            (
+                select_slot.run_if(in_state(InventoryScreen::Closed)),
+                update_slots,
+            )
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Clone, Component, Debug)]
+pub struct Inventory {
+    /// The hotbar first, then storage.
+    pub slots: [Option<ItemStack>; INVENTORY_SLOTS],
+    /// The selected hotbar slot.
+    pub selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: [None; INVENTORY_SLOTS],
+            selected: 0,
+        }
+    }
+}
+
+impl Inventory {
+    pub fn selected_stack(&self) -> Option<ItemStack> {
+        self.slots[self.selected]
+    }
+
+    /// Adds as much of a stack as fits, topping up existing stacks of the item before using
+    /// empty slots, and returns the rest.
+    pub fn add(&mut self, stack: ItemStack, registry: &ItemRegistry) -> Option<ItemStack> {
+        self.add_to(0..INVENTORY_SLOTS, stack, registry)
+    }
+
+    /// Like [`Inventory::add`], but only using the slots in `range`.
+    fn add_to(
+        &mut self,
+        range: Range<usize>,
+        mut stack: ItemStack,
+        registry: &ItemRegistry,
+    ) -> Option<ItemStack> {
+        let max_stack = registry.max_stack(stack.item);
+
+        for slot in self.slots[range.clone()].iter_mut().flatten() {
+            if slot.item == stack.item && slot.count < max_stack {
+                let moved = stack.count.min(max_stack - slot.count);
+
+                slot.count += moved;
+                stack.count -= moved;
+
+                if stack.count == 0 {
+                    return None;
+                }
+            }
+        }
+
+        for slot in self.slots[range].iter_mut().filter(|slot| slot.is_none()) {
+            let moved = stack.count.min(max_stack);
+
+            *slot = Some(ItemStack::new(stack.item, moved));
+            stack.count -= moved;
+
+            if stack.count == 0 {
+                return None;
+            }
+        }
+
+        Some(stack)
+    }
+
+    /// Moves the stack in a slot between the hotbar and storage, as much as fits.
+    pub fn quick_move(&mut self, slot: usize, registry: &ItemRegistry) {
+        let Some(stack) = self.slots[slot].take() else {
+            return;
+        };
+
+        let range = if slot < HOTBAR_SLOTS {
+            HOTBAR_SLOTS..INVENTORY_SLOTS
+        } else {
+            0..HOTBAR_SLOTS
+        };
+
+        self.slots[slot] = self.add_to(range, stack, registry);
+    }
+
+    /// Removes up to `count` items from the selected slot, returning what was removed.
+    pub fn take_selected(&mut self, count: u8) -> Option<ItemStack> {
+        let slot = &mut self.slots[self.selected];
+        let stack = slot.as_mut()?;
+        let taken = count.min(stack.count);
+
+        stack.count -= taken;
+
+        if stack.count == 0 {
+            *slot = None;
+        }
+
+        Some(ItemStack::new(stack.item, taken))
+    }
+
+    /// The first slot holding the item.
+    pub fn find(&self, item: ItemId) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.is_some_and(|stack| stack.item == item))
+    }
+}
+
+/// Clicks a slot with the primary button while `cursor` is held: picks up the slot's stack,
+/// puts the held stack down, tops up the slot's stack or swaps the two.
+pub fn click_slot(
+    slot: &mut Option<ItemStack>,
+    cursor: &mut Option<ItemStack>,
+    registry: &ItemRegistry,
+) {
+    if let (Some(stack), Some(held)) = (slot.as_mut(), cursor.as_mut()) {
+        if stack.item == held.item {
+            let room = registry.max_stack(stack.item).saturating_sub(stack.count);
+            let moved = held.count.min(room);
+
+            stack.count += moved;
+            held.count -= moved;
+
+            if held.count == 0 {
+                *cursor = None;
+            }
+
+            return;
+        }
+    }
+
+    std::mem::swap(slot, cursor);
+}
+
+/// Clicks a slot with the secondary button while `cursor` is held: picks up half of the
+/// slot's stack, rounding up, or puts down one of the held items.
+pub fn split_click_slot(
+    slot: &mut Option<ItemStack>,
+    cursor: &mut Option<ItemStack>,
+    registry: &ItemRegistry,
+) {
+    let Some(held) = cursor.as_mut() else {
+        let Some(stack) = slot.as_mut() else {
+            return;
+        };
+
+        let taken = stack.count.div_ceil(2);
+
+        *cursor = Some(ItemStack::new(stack.item, taken));
+        stack.count -= taken;
+
+        if stack.count == 0 {
+            *slot = None;
+        }
+
+        return;
+    };
+
+    match slot {
+        Some(stack) if stack.item == held.item => {
+            if stack.count >= registry.max_stack(stack.item) {
+                return;
+            }
+
+            stack.count += 1;
+        }
+        Some(_) => return,
+        empty => *empty = Some(ItemStack::new(held.item, 1)),
+    }
+
+    held.count -= 1;
+
+    if held.count == 0 {
+        *cursor = None;
+    }
+}
+
+/// Where the items shown by a slot in the UI are.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Slot {
+    Inventory(usize),
+    Crafting(usize),
+    /// What the crafting grid makes.
+    CraftingOutput,
+}
+
+/// A slot shown in the UI.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct SlotNode(pub Slot);
+
+/// The icon of the item in a slot.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct SlotIcon(pub Slot);
+
+/// The number of items in a slot.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct SlotCount(pub Slot);
+
+/// Spawns the UI of a slot, its contents are filled in by [`update_slots`].
+pub fn spawn_slot(builder: &mut ChildBuilder, slot: Slot, state: &State, font: &Handle<Font>) {
+    builder
+        .spawn((
+            SlotNode(slot),
+            Node {
+                width: Val::Px(SLOT_SIZE),
+                height: Val::Px(SLOT_SIZE),
+                border: UiRect::all(Val::Px(2.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BorderColor(SLOT_BORDER),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            Interaction::default(),
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                SlotIcon(slot),
+                ImageNode::from_atlas_image(
+                    state.texture_atlas.clone(),
+                    TextureAtlas {
+                        layout: state.atlas_layout.clone(),
+                        index: 0,
+                    },
+                ),
+                Node {
+                    width: Val::Px(SLOT_SIZE - 12.0),
+                    height: Val::Px(SLOT_SIZE - 12.0),
+                    ..default()
+                },
+                Visibility::Hidden,
+            ));
+
+            builder.spawn((
+                SlotCount(slot),
+                Text::default(),
+                TextColor(Color::WHITE),
+                TextFont {
+                    font: font.clone(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Node {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(2.0),
+                    bottom: Val::Px(0.0),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn spawn_hotbar(mut commands: Commands, asset_server: Res<AssetServer>, state: Res<State>) {
+    let font = asset_server.load("fonts/RobotoMono-Regular.ttf");
+
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::FlexEnd,
+            padding: UiRect::bottom(Val::Px(8.0)),
+            ..default()
+        })
+        .with_children(|builder| {
+            for index in 0..HOTBAR_SLOTS {
+                spawn_slot(builder, Slot::Inventory(index), &state, &font);
+            }
+        });
+}
+
+/// Selects a hotbar slot with the number keys, or the next or previous one by scrolling.
+fn select_slot(player: Single<(&ActionState<Action>, &mut Inventory), With<PlayerBody>>) {
+    let (action_state, mut inventory) = player.into_inner();
+    let mut selected = inventory.selected;
+
+    for slot in 0..HOTBAR_SLOTS {
+        if action_state.just_pressed(&Action::Hotbar(slot as u8)) {
+            selected = slot;
+        }
+    }
+
+    let scroll = action_state.value(&Action::Scroll);
+
+    if scroll > 0.0 {
+        selected = (selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+    } else if scroll < 0.0 {
+        selected = (selected + 1) % HOTBAR_SLOTS;
+    }
+
+    if inventory.selected != selected {
+        inventory.selected = selected;
+    }
+}
+
+/// Shows the player's items in every slot in the UI, and highlights the selected slot.
+pub fn update_slots(
+    registry: Res<ItemRegistry>,
+    recipes: Res<RecipeRegistry>,
+    player: Single<(Ref<Inventory>, Ref<CraftingGrid>), With<PlayerBody>>,
+    mut slots: Query<(Ref<SlotNode>, &mut BorderColor)>,
+    mut icons: Query<(Ref<SlotIcon>, &mut ImageNode, &mut Visibility)>,
+    mut counts: Query<(Ref<SlotCount>, &mut Text)>,
+) {
+    let (inventory, grid) = player.into_inner();
+
+    // Update newly spawned slots even if the items didn't change.
+    let changed = inventory.is_changed() || grid.is_changed();
+    let contents = |slot: Slot| match slot {
+        Slot::Inventory(index) => inventory.slots[index],
+        Slot::Crafting(index) => grid.slots[index],
+        Slot::CraftingOutput => grid.output(&recipes),
+    };
+
+    for (slot, mut border) in &mut slots {
+        if changed || slot.is_added() {
+            border.0 = if slot.0 == Slot::Inventory(inventory.selected) {
+                SELECTED_SLOT_BORDER
+            } else {
+                SLOT_BORDER
+            };
+        }
+    }
+
+    for (icon, mut image, mut visibility) in &mut icons {
+        if !changed && !icon.is_added() {
+            continue;
+        }
+
+        let item = contents(icon.0).and_then(|stack| registry.get(stack.item));
+
+        match (item, image.texture_atlas.as_mut()) {
+            (Some(item), Some(atlas)) => {
+                atlas.index = item.icon as usize;
+                *visibility = Visibility::Inherited;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+
+    for (count, mut text) in &mut counts {
+        if !changed && !count.is_added() {
+            continue;
+        }
+
+        text.0 = match contents(count.0) {
+            Some(stack) if stack.count > 1 => stack.count.to_string(),
+            _ => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use crate::item::ItemDef;
+    use std::collections::HashMap;
+
+    /// Stone, stacking to 64, and pearls, stacking to 16.
+    fn items() -> (ItemRegistry, ItemId, ItemId) {
+        let pearl = ItemDef {
+            name: "pearl".to_string(),
+            texture: "pearl.png".to_string(),
+            max_stack: 16,
+            tool: None,
+            food: None,
+        };
+        let items = ItemRegistry::new(&registry(&["stone"]), [pearl], &HashMap::new(), 0);
+        let stone = items.by_name("stone").unwrap();
+        let pearl = items.by_name("pearl").unwrap();
+
+        (items, stone, pearl)
+    }
+
+    fn stack(item: ItemId, count: u8) -> Option<ItemStack> {
+        Some(ItemStack::new(item, count))
+    }
+
+    #[test]
+    fn add_tops_up_stacks_before_empty_slots() {
+        let (items, stone, _) = items();
+        let mut inventory = Inventory::default();
+
+        inventory.slots[5] = stack(stone, 60);
+        inventory.slots[20] = stack(stone, 63);
+
+        assert_eq!(inventory.add(ItemStack::new(stone, 10), &items), None);
+        assert_eq!(inventory.slots[5], stack(stone, 64));
+        assert_eq!(inventory.slots[20], stack(stone, 64));
+        assert_eq!(inventory.slots[0], stack(stone, 5));
+    }
+
+    #[test]
+    fn add_splits_large_stacks_and_returns_the_rest() {
+        let (items, stone, pearl) = items();
+        let mut inventory = Inventory::default();
+
+        assert_eq!(inventory.add(ItemStack::new(pearl, 40), &items), None);
+        assert_eq!(
+            inventory.slots[..3],
+            [stack(pearl, 16), stack(pearl, 16), stack(pearl, 8)]
+        );
+
+        inventory.slots[3..].fill(stack(stone, 64));
+
+        assert_eq!(
+            inventory.add(ItemStack::new(pearl, 20), &items),
+            stack(pearl, 12)
+        );
+        assert_eq!(inventory.slots[2], stack(pearl, 16));
+    }
+
+    #[test]
+    fn quick_move_moves_between_hotbar_and_storage() {
+        let (items, stone, pearl) = items();
+        let mut inventory = Inventory::default();
+
+        inventory.slots[0] = stack(stone, 64);
+        inventory.slots[HOTBAR_SLOTS + 1] = stack(stone, 50);
+        inventory.quick_move(0, &items);
+
+        assert_eq!(inventory.slots[0], None);
+        assert_eq!(inventory.slots[HOTBAR_SLOTS], stack(stone, 50));
+        assert_eq!(inventory.slots[HOTBAR_SLOTS + 1], stack(stone, 64));
+
+        // Whatever doesn't fit in a full hotbar stays where it was.
+        inventory.slots[..HOTBAR_SLOTS].fill(stack(pearl, 16));
+        inventory.quick_move(HOTBAR_SLOTS, &items);
+
+        assert_eq!(inventory.slots[HOTBAR_SLOTS], stack(stone, 50));
+    }
+
+    #[test]
+    fn take_selected_takes_at_most_the_stack() {
+        let (_, stone, _) = items();
+        let mut inventory = Inventory::default();
+
+        inventory.selected = 2;
+        inventory.slots[2] = stack(stone, 3);
+
+        assert_eq!(inventory.take_selected(1), stack(stone, 1));
+        assert_eq!(inventory.take_selected(5), stack(stone, 2));
+        assert_eq!(inventory.slots[2], None);
+        assert_eq!(inventory.take_selected(1), None);
+    }
+
+    #[test]
+    fn click_slot_picks_up_puts_down_tops_up_and_swaps() {
+        let (items, stone, pearl) = items();
+        let mut slot = stack(stone, 60);
+        let mut cursor = None;
+
+        click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (None, stack(stone, 60)));
+
+        click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 60), None));
+
+        cursor = stack(stone, 10);
+        click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 64), stack(stone, 6)));
+
+        cursor = stack(pearl, 2);
+        click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(pearl, 2), stack(stone, 64)));
+    }
+
+    #[test]
+    fn split_click_slot_takes_half_rounding_up() {
+        let (items, stone, _) = items();
+        let mut slot = stack(stone, 7);
+        let mut cursor = None;
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 3), stack(stone, 4)));
+
+        let mut slot = stack(stone, 1);
+        let mut cursor = None;
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (None, stack(stone, 1)));
+    }
+
+    #[test]
+    fn split_click_slot_puts_down_one() {
+        let (items, stone, pearl) = items();
+        let mut slot = None;
+        let mut cursor = stack(stone, 2);
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 1), stack(stone, 1)));
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 2), None));
+
+        // Full stacks and other items are left alone.
+        let mut slot = stack(stone, 64);
+        let mut cursor = stack(stone, 2);
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(stone, 64), stack(stone, 2)));
+
+        let mut slot = stack(pearl, 1);
+
+        split_click_slot(&mut slot, &mut cursor, &items);
+        assert_eq!((slot, cursor), (stack(pearl, 1), stack(stone, 2)));
+    }
+}
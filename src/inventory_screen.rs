@@ -0,0 +1,436 @@
+//! The inventory screen, toggled with `Action::Inventory` or opened by using a crafting table,
+//! for moving stacks around with the mouse and crafting. The cursor is only shown while the
+//! screen is open.
+
+use crate::crafting::{CraftingGrid, RecipeRegistry, INVENTORY_GRID};
+use crate::dropped_item::DropItem;
+use crate::inventory::{
+    click_slot, spawn_slot, split_click_slot, update_slots, Inventory, Slot, SlotNode,
+    HOTBAR_SLOTS, INVENTORY_SLOTS,
+};
+use crate::item::{ItemRegistry, ItemStack};
+use crate::{Action, GameState, PlayerBody, PlayerEye};
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use leafwing_input_manager::prelude::*;
+
+pub struct InventoryScreenPlugin;
+
+impl Plugin for InventoryScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<InventoryScreen>()
+            .init_resource::<CursorStack>()
+            .add_systems(OnEnter(InventoryScreen::Closed), grab_cursor)
+            .add_systems(
+                OnEnter(InventoryScreen::Open),
+                (release_cursor, spawn_screen),
+            )
+            .add_systems(OnExit(InventoryScreen::Open), close_screen)
+            .add_systems(
+                Update,
+                (
+                    toggle_screen,
+                    (click_slots, update_cursor_stack)
+                        .chain()
+                        .before(update_slots)
+                        .run_if(in_state(InventoryScreen::Open)),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Whether the inventory screen is open, only while in game.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, SubStates)]
+#[source(GameState = GameState::InGame)]
+pub enum InventoryScreen {
+    #[default]
+    Closed,
+    Open,
+}
+
+/// The stack picked up by the cursor, if any.
+#[derive(Debug, Default, Deref, DerefMut, Resource)]
+pub struct CursorStack(pub Option<ItemStack>);
+
+/// The root node of the inventory screen.
+#[derive(Component)]
+struct Screen;
+
+/// The icon following the cursor, showing the [`CursorStack`].
+#[derive(Component)]
+struct CursorIcon;
+
+/// The number of items on the cursor.
+#[derive(Component)]
+struct CursorCount;
+
+/// The slot a stack was picked up from by pressing the primary button, to tell drags from
+/// clicks.
+#[derive(Debug, Default)]
+struct DragStart(Option<Slot>);
+
+fn toggle_screen(
+    current: Res<State<InventoryScreen>>,
+    mut next: ResMut<NextState<InventoryScreen>>,
+    action_state: Single<&ActionState<Action>, With<PlayerBody>>,
+) {
+    if !action_state.just_pressed(&Action::Inventory) {
+        return;
+    }
+
+    next.set(match current.get() {
+        InventoryScreen::Closed => InventoryScreen::Open,
+        InventoryScreen::Open => InventoryScreen::Closed,
+    });
+}
+
+fn grab_cursor(mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    window.cursor_options.grab_mode = CursorGrabMode::Locked;
+    window.cursor_options.visible = false;
+}
+
+fn release_cursor(mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    window.cursor_options.grab_mode = CursorGrabMode::None;
+    window.cursor_options.visible = true;
+}
+
+fn spawn_row(
+    builder: &mut ChildBuilder,
+    slots: impl IntoIterator<Item = Slot>,
+    state: &crate::State,
+    font: &Handle<Font>,
+) {
+    builder
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            ..default()
+        })
+        .with_children(|builder| {
+            for slot in slots {
+                spawn_slot(builder, slot, state, font);
+            }
+        });
+}
+
+fn spawn_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state: Res<crate::State>,
+    grid: Single<&CraftingGrid, With<PlayerBody>>,
+) {
+    let font = asset_server.load("fonts/RobotoMono-Regular.ttf");
+    let width = grid.width;
+
+    commands
+        .spawn((
+            Screen,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.4)),
+        ))
+        .with_children(|builder| {
+            builder
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+                ))
+                .with_children(|builder| {
+                    builder
+                        .spawn(Node {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(12.0),
+                            margin: UiRect::bottom(Val::Px(8.0)),
+                            ..default()
+                        })
+                        .with_children(|builder| {
+                            builder
+                                .spawn(Node {
+                                    flex_direction: FlexDirection::Column,
+                                    ..default()
+                                })
+                                .with_children(|builder| {
+                                    for row in 0..width {
+                                        let slots = (0..width)
+                                            .map(|column| Slot::Crafting(row * width + column));
+
+                                        spawn_row(builder, slots, &state, &font);
+                                    }
+                                });
+
+                            builder.spawn((
+                                Text::new("->"),
+                                TextColor(Color::WHITE),
+                                TextFont {
+                                    font: font.clone(),
+                                    ..default()
+                                },
+                            ));
+
+                            spawn_slot(builder, Slot::CraftingOutput, &state, &font);
+                        });
+
+                    for start in (HOTBAR_SLOTS..INVENTORY_SLOTS).step_by(HOTBAR_SLOTS) {
+                        let slots = (start..start + HOTBAR_SLOTS).map(Slot::Inventory);
+
+                        spawn_row(builder, slots, &state, &font);
+                    }
+
+                    // The hotbar goes below storage, a little apart.
+                    builder.spawn(Node {
+                        height: Val::Px(8.0),
+                        ..default()
+                    });
+
+                    spawn_row(
+                        builder,
+                        (0..HOTBAR_SLOTS).map(Slot::Inventory),
+                        &state,
+                        &font,
+                    );
+                });
+
+            builder
+                .spawn((
+                    CursorIcon,
+                    ImageNode::from_atlas_image(
+                        state.texture_atlas.clone(),
+                        TextureAtlas {
+                            layout: state.atlas_layout.clone(),
+                            index: 0,
+                        },
+                    ),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(32.0),
+                        height: Val::Px(32.0),
+                        ..default()
+                    },
+                    GlobalZIndex(1),
+                    Visibility::Hidden,
+                ))
+                .with_child((
+                    CursorCount,
+                    Text::default(),
+                    TextColor(Color::WHITE),
+                    TextFont {
+                        font: font.clone(),
+                        font_size: 12.0,
+                        ..default()
+                    },
+                    Node {
+                        position_type: PositionType::Absolute,
+                        right: Val::Px(-4.0),
+                        bottom: Val::Px(-4.0),
+                        ..default()
+                    },
+                ));
+        });
+}
+
+/// Puts the stack on the cursor and the crafting grid back into the inventory, throwing out
+/// whatever doesn't fit, and despawns the screen.
+fn close_screen(
+    mut commands: Commands,
+    mut cursor: ResMut<CursorStack>,
+    mut drops: EventWriter<DropItem>,
+    registry: Res<ItemRegistry>,
+    screen: Single<Entity, With<Screen>>,
+    player: Single<(&mut Inventory, &mut CraftingGrid), With<PlayerBody>>,
+    eye: Single<&GlobalTransform, With<PlayerEye>>,
+) {
+    let (mut inventory, mut grid) = player.into_inner();
+
+    for stack in cursor.take().into_iter().chain(grid.take_all()) {
+        if let Some(rest) = inventory.add(stack, &registry) {
+            drops.send(DropItem {
+                stack: rest,
+                position: eye.translation(),
+                velocity: eye.forward() * 3.0,
+            });
+        }
+    }
+
+    grid.width = INVENTORY_GRID;
+
+    commands.entity(*screen).despawn_recursive();
+}
+
+/// Handles clicking slots with the mouse, the primary button picks up and puts down stacks,
+/// or moves them between the hotbar and storage while holding shift, and the secondary
+/// button splits stacks. Pressing on one slot and releasing on another drags the stack.
+///
+/// Clicking the crafting output crafts once onto the cursor, or as many times as fits into
+/// the inventory while holding shift.
+#[allow(clippy::too_many_arguments)]
+fn click_slots(
+    mut drag_start: Local<DragStart>,
+    mut cursor: ResMut<CursorStack>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    registry: Res<ItemRegistry>,
+    recipes: Res<RecipeRegistry>,
+    slots: Query<(&SlotNode, &Interaction)>,
+    player: Single<(&mut Inventory, &mut CraftingGrid), With<PlayerBody>>,
+) {
+    let (mut inventory, mut grid) = player.into_inner();
+    let hovered = slots
+        .iter()
+        .find(|(_, interaction)| **interaction != Interaction::None)
+        .map(|(slot, _)| slot.0);
+
+    if mouse_buttons.just_released(MouseButton::Left) {
+        if let Some(start) = drag_start.0.take() {
+            let target = hovered
+                .filter(|&slot| slot != start)
+                .and_then(|slot| slot_mut(slot, &mut inventory, &mut grid));
+
+            if let Some(target) = target {
+                click_slot(target, &mut cursor.0, &registry);
+            }
+        }
+    }
+
+    let Some(slot) = hovered else {
+        return;
+    };
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if slot == Slot::CraftingOutput {
+        if !mouse_buttons.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+            return;
+        }
+
+        if shift {
+            craft_into_inventory(&mut inventory, &mut grid, &registry, &recipes);
+        } else {
+            craft_onto_cursor(&mut cursor.0, &mut grid, &registry, &recipes);
+        }
+
+        return;
+    }
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        match slot {
+            Slot::Inventory(index) if shift => inventory.quick_move(index, &registry),
+            Slot::Crafting(index) if shift => {
+                if let Some(stack) = grid.slots[index].take() {
+                    grid.slots[index] = inventory.add(stack, &registry);
+                }
+            }
+            _ => {
+                let picked_up = cursor.is_none();
+
+                if let Some(target) = slot_mut(slot, &mut inventory, &mut grid) {
+                    click_slot(target, &mut cursor.0, &registry);
+                }
+
+                if picked_up && cursor.is_some() {
+                    drag_start.0 = Some(slot);
+                }
+            }
+        }
+    } else if mouse_buttons.just_pressed(MouseButton::Right) {
+        if let Some(target) = slot_mut(slot, &mut inventory, &mut grid) {
+            split_click_slot(target, &mut cursor.0, &registry);
+        }
+    }
+}
+
+/// The items of a slot that holds items, rather than showing a crafting output.
+fn slot_mut<'a>(
+    slot: Slot,
+    inventory: &'a mut Inventory,
+    grid: &'a mut CraftingGrid,
+) -> Option<&'a mut Option<ItemStack>> {
+    match slot {
+        Slot::Inventory(index) => Some(&mut inventory.slots[index]),
+        Slot::Crafting(index) => Some(&mut grid.slots[index]),
+        Slot::CraftingOutput => None,
+    }
+}
+
+/// Crafts once, if the output fits onto the stack on the cursor.
+fn craft_onto_cursor(
+    cursor: &mut Option<ItemStack>,
+    grid: &mut CraftingGrid,
+    registry: &ItemRegistry,
+    recipes: &RecipeRegistry,
+) {
+    let Some(output) = grid.output(recipes) else {
+        return;
+    };
+
+    match cursor {
+        Some(held)
+            if held.item == output.item
+                && held.count.saturating_add(output.count) <= registry.max_stack(output.item) =>
+        {
+            grid.craft(recipes);
+            held.count += output.count;
+        }
+        Some(_) => {}
+        None => *cursor = grid.craft(recipes),
+    }
+}
+
+/// Crafts as many times as the grid allows, while the output fits into the inventory.
+fn craft_into_inventory(
+    inventory: &mut Inventory,
+    grid: &mut CraftingGrid,
+    registry: &ItemRegistry,
+    recipes: &RecipeRegistry,
+) {
+    while let Some(output) = grid.output(recipes) {
+        if inventory.clone().add(output, registry).is_some() {
+            return;
+        }
+
+        if let Some(output) = grid.craft(recipes) {
+            inventory.add(output, registry);
+        }
+    }
+}
+
+/// Moves the cursor icon onto the cursor, showing the item on it.
+fn update_cursor_stack(
+    cursor: Res<CursorStack>,
+    registry: Res<ItemRegistry>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    icon: Single<(&mut Node, &mut ImageNode, &mut Visibility), With<CursorIcon>>,
+    mut count: Single<&mut Text, With<CursorCount>>,
+) {
+    let (mut node, mut image, mut visibility) = icon.into_inner();
+    let stack = cursor.and_then(|stack| Some((stack, registry.get(stack.item)?)));
+
+    let (Some((stack, item)), Some(position)) = (stack, window.cursor_position()) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    count.0 = if stack.count > 1 {
+        stack.count.to_string()
+    } else {
+        String::new()
+    };
+
+    if let Some(atlas) = image.texture_atlas.as_mut() {
+        atlas.index = item.icon as usize;
+    }
+
+    node.left = Val::Px(position.x - 16.0);
+    node.top = Val::Px(position.y - 16.0);
+    *visibility = Visibility::Inherited;
+}
@@ -0,0 +1,222 @@
+use crate::block::BlockRegistry;
+use crate::breaking::Tool;
+use crate::hunger::Food;
+use crate::world::BlockId;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How many of an item fit in a stack, unless its definition says otherwise.
+pub const DEFAULT_MAX_STACK: u8 = 64;
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ItemDef>()
+            .init_asset_loader::<ItemDefLoader>();
+    }
+}
+
+/// The definition of an item that isn't a block, loaded from a `.item.ron` file.
+///
+/// Every block is also an item with the same name, so blocks don't need item files.
+#[derive(Asset, Clone, Debug, Deserialize, TypePath)]
+pub struct ItemDef {
+    pub name: String,
+    pub texture: String,
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u8,
+    #[serde(default)]
+    pub tool: Option<Tool>,
+    #[serde(default)]
+    pub food: Option<Food>,
+}
+
+fn default_max_stack() -> u8 {
+    DEFAULT_MAX_STACK
+}
+
+#[derive(Default)]
+struct ItemDefLoader;
+
+#[derive(Debug)]
+pub enum ItemDefLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for ItemDefLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read item definition: {error}"),
+            Self::Ron(error) => write!(f, "failed to parse item definition: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ItemDefLoaderError {}
+
+impl AssetLoader for ItemDefLoader {
+    type Asset = ItemDef;
+    type Settings = ();
+    type Error = ItemDefLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(ItemDefLoaderError::Io)?;
+
+        ron::de::from_bytes(&bytes).map_err(ItemDefLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["item.ron"]
+    }
+}
+
+/// Identifies a type of item, an index into the [`ItemRegistry`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ItemId(pub u16);
+
+/// A registered item type.
+#[derive(Clone, Debug)]
+pub struct ItemType {
+    pub name: String,
+    /// The atlas index of the item's icon.
+    pub icon: u32,
+    pub max_stack: u8,
+    /// The block placed when using the item.
+    pub block: Option<BlockId>,
+    pub tool: Option<Tool>,
+    /// What eating the item restores.
+    pub food: Option<Food>,
+}
+
+/// Every item type, blocks first, in the order of their block ids.
+#[derive(Clone, Debug, Resource)]
+pub struct ItemRegistry {
+    items: Vec<ItemType>,
+    names: HashMap<String, ItemId>,
+    blocks: HashMap<BlockId, ItemId>,
+}
+
+impl ItemRegistry {
+    /// Builds the registry from every block in `blocks` and item definitions.
+    ///
+    /// `texture_map` maps texture file names to atlas indices, textures that are
+    /// not in the atlas use `missing_texture` instead.
+    pub fn new(
+        blocks: &BlockRegistry,
+        defs: impl IntoIterator<Item = ItemDef>,
+        texture_map: &HashMap<String, u32>,
+        missing_texture: u32,
+    ) -> Self {
+        let mut registry = Self {
+            items: Vec::new(),
+            names: HashMap::new(),
+            blocks: HashMap::new(),
+        };
+
+        for (id, block) in blocks.iter() {
+            // Blocks are shown by their front face.
+            let item = registry.insert(ItemType {
+                name: block.name.clone(),
+                icon: block.textures[0],
+                max_stack: DEFAULT_MAX_STACK,
+                block: Some(id),
+                tool: None,
+                food: None,
+            });
+
+            registry.blocks.insert(id, item);
+        }
+
+        let mut defs: Vec<_> = defs.into_iter().collect();
+
+        // Keep ids the same between runs, folders aren't loaded in any particular order.
+        defs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for def in defs {
+            if registry.by_name(&def.name).is_some() {
+                warn!("item {} is defined twice, skipping", def.name);
+                continue;
+            }
+
+            let icon = texture_map.get(&def.texture).copied().unwrap_or_else(|| {
+                warn!("item {} is missing texture {}", def.name, def.texture);
+                missing_texture
+            });
+
+            registry.insert(ItemType {
+                name: def.name,
+                icon,
+                max_stack: def.max_stack.max(1),
+                block: None,
+                tool: def.tool,
+                food: def.food,
+            });
+        }
+
+        registry
+    }
+
+    fn insert(&mut self, item: ItemType) -> ItemId {
+        let id = ItemId(self.items.len() as u16);
+
+        self.names.insert(item.name.clone(), id);
+        self.items.push(item);
+
+        id
+    }
+
+    pub fn get(&self, id: ItemId) -> Option<&ItemType> {
+        self.items.get(id.0 as usize)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<ItemId> {
+        self.names.get(name).copied()
+    }
+
+    /// The item of a block, if it's registered.
+    pub fn by_block(&self, block: BlockId) -> Option<ItemId> {
+        self.blocks.get(&block).copied()
+    }
+
+    /// Iterates over every registered item.
+    pub fn iter(&self) -> impl Iterator<Item = (ItemId, &ItemType)> {
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (ItemId(index as u16), item))
+    }
+
+    /// How many of an item fit in a stack, unregistered items don't stack.
+    pub fn max_stack(&self, id: ItemId) -> u8 {
+        self.get(id).map_or(1, |item| item.max_stack)
+    }
+}
+
+/// A number of the same item.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ItemStack {
+    pub item: ItemId,
+    pub count: u8,
+}
+
+impl ItemStack {
+    pub fn new(item: ItemId, count: u8) -> Self {
+        Self { item, count }
+    }
+}
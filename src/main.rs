@@ -1,4 +1,27 @@
+use self::anvil::import_world;
+use self::block::{BlockDef, BlockPlugin, BlockRegistry, Placement};
+use self::breaking::BreakingPlugin;
+use self::console::ConsolePlugin;
+use self::crafting::{CraftingGrid, CraftingPlugin, RecipeDef, RecipeRegistry};
+use self::daylight::{DaylightPlugin, Sun, TimeRate};
+use self::dropped_item::DroppedItemPlugin;
+use self::health::{GameRules, Health, HealthPlugin};
+use self::hunger::{Hunger, HungerPlugin};
+use self::interaction::InteractionPlugin;
+use self::inventory::{Inventory, InventoryPlugin, HOTBAR_SLOTS};
+use self::inventory_screen::InventoryScreenPlugin;
+use self::item::{ItemDef, ItemPlugin, ItemRegistry, ItemStack};
+use self::material::{BlockAtlas, BlockMaterial};
+use self::mesh::MeshingMode;
 use self::physics::{CharacterControllerBundle, CharacterControllerPlugin};
+use self::raycast::{RaycastPlugin, TargetBlock};
+use self::save::{LevelInfo, PlayerData, SavePlugin, WorldSave};
+use self::schematic::{SchematicPlugin, SchematicSettings, SchematicVersion};
+use self::streaming::{ChunkMaterial, ChunkStreamingPlugin, MeshingRegistry, RenderDistance};
+use self::world::{BlockId, ChunkMap, WorldTime, CHUNK_SIZE};
+use self::worldgen::{
+    GeneratorKind, NoiseGenerator, SuperflatGenerator, TerrainBlocks, WorldGen, WorldGenerator,
+};
 use avian3d::math::*;
 use avian3d::prelude::*;
 use bevy::asset::LoadedFolder;
@@ -9,30 +32,166 @@ use bevy::render::settings::{RenderCreation, WgpuFeatures, WgpuSettings};
 use bevy::render::RenderPlugin;
 use leafwing_input_manager::prelude::*;
 use std::collections::HashMap;
-
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod anvil;
+mod block;
+mod breaking;
+mod console;
+mod crafting;
+mod daylight;
+mod dropped_item;
+mod health;
+mod hunger;
+mod interaction;
+mod inventory;
+mod inventory_screen;
+mod item;
+mod material;
 mod mesh;
+mod nbt;
 mod physics;
+mod raycast;
+mod save;
+mod schematic;
+mod streaming;
+mod world;
+mod worldgen;
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, States)]
 enum GameState {
     #[default]
     Setup,
     InGame,
+    /// The player died, showing the death screen until they respawn.
+    Dead,
 }
 
 #[derive(Debug, Resource)]
 struct State {
-    block: Handle<Mesh>,
     texture_atlas: Handle<Image>,
-    texture_map: HashMap<String, u32>,
-    material: Handle<StandardMaterial>,
+    atlas_layout: Handle<TextureAtlasLayout>,
+}
+
+/// Options for the world, from the command line.
+#[derive(Debug, Resource)]
+struct WorldSettings {
+    world: String,
+    seed: u64,
+    generator: GeneratorKind,
+    render_distance: Option<i32>,
+    /// A Minecraft Java Edition world to import into the world before playing.
+    import: Option<PathBuf>,
+    schematic: SchematicSettings,
+    /// Whether the player keeps their inventory when they die, rather than dropping it.
+    keep_inventory: bool,
+    /// The time of day to start at, as a fraction of the day from sunrise.
+    time_of_day: Option<f64>,
+    /// How many times faster than usual the day passes.
+    time_rate: f64,
+}
+
+impl WorldSettings {
+    /// Parses `--world <name>`, `--seed <seed>`, `--superflat`, `--render-distance <chunks>`,
+    /// `--import <vanilla world>`, `--schematic <path>`, `--schematic-v2`, `--keep-inventory`,
+    /// `--time <time of day>` and `--time-rate <multiplier>`, the seed is random if unspecified.
+    /// The seed and generator only apply to new worlds.
+    fn from_args() -> Self {
+        let mut world = String::from("world");
+        let mut seed = None;
+        let mut generator = GeneratorKind::Noise;
+        let mut render_distance = None;
+        let mut import = None;
+        let mut schematic = SchematicSettings::default();
+        let mut keep_inventory = false;
+        let mut time_of_day = None;
+        let mut time_rate = 1.0;
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--world" => world = args.next().unwrap_or(world),
+                "--seed" => seed = args.next().and_then(|seed| seed.parse().ok()),
+                "--superflat" => generator = GeneratorKind::Superflat,
+                "--render-distance" => {
+                    render_distance = args.next().and_then(|distance| distance.parse().ok())
+                }
+                "--import" => import = args.next().map(PathBuf::from),
+                "--schematic" => schematic.path = args.next().map_or(schematic.path, PathBuf::from),
+                "--schematic-v2" => schematic.version = SchematicVersion::V2,
+                "--keep-inventory" => keep_inventory = true,
+                "--time" => {
+                    time_of_day = args
+                        .next()
+                        .and_then(|time| daylight::parse_time_of_day(&time))
+                }
+                "--time-rate" => {
+                    time_rate = args
+                        .next()
+                        .and_then(|rate| rate.parse().ok())
+                        .unwrap_or(time_rate)
+                }
+                _ => warn!("unknown argument {arg}"),
+            }
+        }
+
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64)
+        });
+
+        Self {
+            world,
+            seed,
+            generator,
+            render_distance,
+            import,
+            schematic,
+            keep_inventory,
+            time_of_day,
+            time_rate,
+        }
+    }
 }
 
 #[derive(Resource, Default)]
 struct TextureFolder(Handle<LoadedFolder>);
 
+#[derive(Resource, Default)]
+struct BlockFolder(Handle<LoadedFolder>);
+
+#[derive(Resource, Default)]
+struct ItemFolder(Handle<LoadedFolder>);
+
+#[derive(Resource, Default)]
+struct RecipeFolder(Handle<LoadedFolder>);
+
+/// Every definition of type `A` in a loaded folder.
+fn folder_defs<A: Asset + Clone>(
+    loaded_folders: &Assets<LoadedFolder>,
+    folder: &Handle<LoadedFolder>,
+    defs: &Assets<A>,
+) -> Vec<A> {
+    let Some(folder) = loaded_folders.get(folder) else {
+        return Vec::new();
+    };
+
+    folder
+        .handles
+        .iter()
+        .filter_map(|handle| defs.get(handle.id().typed_unchecked::<A>()))
+        .cloned()
+        .collect()
+}
+
 #[derive(Component)]
-struct Block;
+struct Selection;
+
+/// The size of the box the player collides with blocks as, in blocks.
+const PLAYER_SIZE: Vec3 = Vec3::new(0.6, 1.8, 0.6);
 
 #[derive(Component)]
 struct PlayerBody;
@@ -58,11 +217,35 @@ enum Action {
     Attack,
     Pick,
     Use,
+    #[actionlike(Axis)]
+    Scroll,
+    /// Selects a hotbar slot, counting from 0.
+    Hotbar(u8),
+    Inventory,
+    SelectFrom,
+    SelectTo,
+    Rotate,
+    Export,
+    Paste,
+    /// Skips ahead to the next sunrise, noon, sunset or midnight.
+    SkipTime,
 }
 
 impl Action {
     pub fn input_map() -> InputMap<Self> {
-        InputMap::default()
+        const HOTBAR_KEYS: [KeyCode; HOTBAR_SLOTS] = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+
+        let input_map = InputMap::default()
             .with_dual_axis(Action::Look, MouseMove::default())
             .with_dual_axis(Action::Move, VirtualDPad::wasd())
             .with(Action::Jump, KeyCode::Space)
@@ -71,10 +254,32 @@ impl Action {
             .with(Action::Attack, MouseButton::Left)
             .with(Action::Pick, MouseButton::Middle)
             .with(Action::Use, MouseButton::Right)
+            .with_axis(Action::Scroll, MouseScrollAxis::Y)
+            .with(Action::Inventory, KeyCode::KeyE)
+            .with(Action::SelectFrom, KeyCode::BracketLeft)
+            .with(Action::SelectTo, KeyCode::BracketRight)
+            .with(Action::Rotate, KeyCode::KeyR)
+            .with(Action::Export, KeyCode::KeyX)
+            .with(Action::Paste, KeyCode::KeyV)
+            .with(Action::SkipTime, KeyCode::KeyT);
+
+        HOTBAR_KEYS
+            .into_iter()
+            .enumerate()
+            .fold(input_map, |input_map, (slot, key)| {
+                input_map.with(Action::Hotbar(slot as u8), key)
+            })
     }
 }
 
 fn main() {
+    let settings = WorldSettings::from_args();
+    let mut render_distance = RenderDistance::default();
+
+    if let Some(distance) = settings.render_distance {
+        render_distance.horizontal = distance;
+    }
+
     App::new()
         .add_plugins((
             DefaultPlugins.set(RenderPlugin {
@@ -85,132 +290,317 @@ fn main() {
                 ..default()
             }),
             InputManagerPlugin::<Action>::default(),
-            MeshPickingPlugin,
             PhysicsPlugins::default(),
             CharacterControllerPlugin,
+            RaycastPlugin,
+            InteractionPlugin,
+            BreakingPlugin,
+            ConsolePlugin,
+            InventoryPlugin,
+            InventoryScreenPlugin,
+            BlockPlugin,
+            ItemPlugin,
+            CraftingPlugin,
+            DaylightPlugin,
+            DroppedItemPlugin,
+            HealthPlugin,
+            HungerPlugin,
+            ChunkStreamingPlugin,
+            SavePlugin,
+            SchematicPlugin,
             WireframePlugin,
+            MaterialPlugin::<BlockMaterial>::default(),
         ))
         .insert_resource(WireframeConfig {
             global: false,
             default_color: Color::WHITE,
         })
+        .init_resource::<MeshingMode>()
+        .insert_resource(settings.schematic.clone())
+        .insert_resource(TimeRate(settings.time_rate))
+        .insert_resource(GameRules {
+            keep_inventory: settings.keep_inventory,
+        })
+        .insert_resource(settings)
+        .insert_resource(render_distance)
         .init_state::<GameState>()
         .add_systems(OnEnter(GameState::Setup), setup)
         .add_systems(Update, loading.run_if(in_state(GameState::Setup)))
-        .add_systems(OnExit(GameState::Setup), finalize)
-        .add_systems(Update, update_hud.run_if(in_state(GameState::InGame)))
+        .add_systems(OnExit(GameState::Setup), (finalize, load_recipes).chain())
+        .add_systems(
+            Update,
+            (
+                update_selection.after(raycast::update_target_block),
+                update_hud,
+            )
+                .run_if(in_state(GameState::InGame)),
+        )
         .run();
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(TextureFolder(asset_server.load_folder("textures")));
+    commands.insert_resource(BlockFolder(asset_server.load_folder("blocks")));
+    commands.insert_resource(ItemFolder(asset_server.load_folder("items")));
+    commands.insert_resource(RecipeFolder(asset_server.load_folder("recipes")));
 }
 
 fn loading(
+    asset_server: Res<AssetServer>,
     mut next_state: ResMut<NextState<GameState>>,
     texture_folder: Res<TextureFolder>,
-    mut events: EventReader<AssetEvent<LoadedFolder>>,
+    block_folder: Res<BlockFolder>,
+    item_folder: Res<ItemFolder>,
+    recipe_folder: Res<RecipeFolder>,
 ) {
-    for event in events.read() {
-        if event.is_loaded_with_dependencies(&texture_folder.0) {
-            next_state.set(GameState::InGame);
-        }
+    if asset_server.is_loaded_with_dependencies(&texture_folder.0)
+        && asset_server.is_loaded_with_dependencies(&block_folder.0)
+        && asset_server.is_loaded_with_dependencies(&item_folder.0)
+        && asset_server.is_loaded_with_dependencies(&recipe_folder.0)
+    {
+        next_state.set(GameState::InGame);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn finalize(
     asset_server: Res<AssetServer>,
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut block_materials: ResMut<Assets<BlockMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    block_defs: Res<Assets<BlockDef>>,
+    item_defs: Res<Assets<ItemDef>>,
     loaded_folders: Res<Assets<LoadedFolder>>,
     texture_folder: Res<TextureFolder>,
+    block_folder: Res<BlockFolder>,
+    item_folder: Res<ItemFolder>,
+    settings: Res<WorldSettings>,
+    render_distance: Res<RenderDistance>,
 ) {
+    let missing_texture = images.add(block::missing_texture());
     let texture_folder = loaded_folders.get(&texture_folder.0).unwrap();
     let mut builder = TextureAtlasBuilder::default();
-    let mut texture_map = HashMap::new();
-    let mut index = 0;
+    let mut texture_ids = vec![("missing".to_string(), missing_texture.id())];
 
     for handle in texture_folder.handles.iter() {
         let id = handle.id().typed_unchecked::<Image>();
-        let path = handle
-            .path()
-            .unwrap()
-            .path()
-            .file_name()
-            .unwrap()
-            .to_string_lossy();
+        let Some(path) = handle.path().and_then(|path| path.path().file_name()) else {
+            continue;
+        };
 
-        let texture = images.get(id).unwrap();
+        texture_ids.push((path.to_string_lossy().into_owned(), id));
+    }
 
-        builder.add_texture(Some(id), texture);
-        texture_map.insert(path.to_string(), index);
+    for (path, id) in &texture_ids {
+        let Some(texture) = images.get(*id) else {
+            warn!("texture {path} failed to load");
+            continue;
+        };
 
-        index += 1;
+        builder.add_texture(Some(*id), texture);
+    }
 
-        info!("loaded texture {path} into atlas at {index}");
+    let (layout, sources, mut image) = builder.build().unwrap();
+    let mut texture_map = HashMap::new();
+
+    for (path, id) in texture_ids {
+        if let Some(index) = sources.texture_index(id) {
+            info!("loaded texture {path} into atlas at {index}");
+            texture_map.insert(path, index as u32);
+        }
     }
 
-    let (_layout, _sources, mut image) = builder.build().unwrap();
+    let texture_uvs = layout
+        .textures
+        .iter()
+        .map(|rect| rect.min.as_vec2() / layout.size.as_vec2())
+        .collect();
 
     image.sampler = ImageSampler::nearest();
     let texture_atlas = images.add(image);
 
-    let mesh = mesh::new_block(
-        texture_map["grass_side.png"], // Front
-        texture_map["grass_side.png"], // Back
-        texture_map["grass_side.png"], // Right
-        texture_map["grass_side.png"], // Left
-        texture_map["grass_top.png"],  // Top
-        texture_map["dirt.png"],       // Bottom
+    let registry = BlockRegistry::new(
+        folder_defs(&loaded_folders, &block_folder.0, &block_defs),
+        &texture_map,
+        texture_uvs,
+        texture_map["missing"],
     );
 
-    let material = materials.add(StandardMaterial {
-        base_color: Color::WHITE,
-        base_color_texture: Some(texture_atlas.clone()),
-        perceptual_roughness: 0.97,
-        reflectance: 0.1,
-        ..default()
+    let material = block_materials.add(BlockMaterial {
+        base: StandardMaterial {
+            base_color: Color::WHITE,
+            perceptual_roughness: 0.97,
+            reflectance: 0.1,
+            alpha_mode: AlphaMode::Mask(0.5),
+            ..default()
+        },
+        extension: BlockAtlas {
+            tile_size: Vec2::splat(mesh::TEXTURE_SIZE) / layout.size.as_vec2(),
+            texture: texture_atlas.clone(),
+        },
     });
 
+    let block = registry
+        .by_name("grass")
+        .or_else(|| registry.iter().next().map(|(id, _)| id))
+        .unwrap_or(BlockId::AIR);
+
+    commands.spawn((
+        Selection,
+        Mesh3d(meshes.add(mesh::new_block(&registry, block))),
+        Wireframe,
+        Transform::from_scale(Vec3::splat(1.001)),
+        Visibility::Hidden,
+    ));
+
+    let items = ItemRegistry::new(
+        &registry,
+        folder_defs(&loaded_folders, &item_folder.0, &item_defs),
+        &texture_map,
+        texture_map["missing"],
+    );
+
+    // Start with a stack of everything that can be placed or used.
+    let mut inventory = Inventory::default();
+
+    for (id, item) in items.iter() {
+        let placeable = item.block.is_none_or(|block| {
+            registry
+                .get(block)
+                .is_some_and(|block| block.placement != Placement::Never)
+        });
+
+        if placeable {
+            inventory.add(ItemStack::new(id, item.max_stack), &items);
+        }
+    }
+
     let state = State {
-        block: meshes.add(mesh),
         texture_atlas,
-        texture_map,
-        material,
+        atlas_layout: atlas_layouts.add(layout),
     };
 
-    for x in 0..=16 {
-        for y in 0..=16 {
-            spawn_block(&mut commands, &state, Vec3::new(x as f32, -10.0, y as f32));
+    let save = WorldSave::new(Path::new("saves").join(&settings.world));
+
+    if let Some(dir) = &settings.import {
+        match import_world(dir, &registry, &save) {
+            Ok(summary) => info!("{summary}"),
+            Err(error) => error!("failed to import {}: {error}", dir.display()),
         }
     }
 
+    let level = save.load_level().unwrap_or_else(|error| {
+        error!(
+            "failed to load world {}, starting a new one: {error}",
+            settings.world
+        );
+        None
+    });
+
+    let (seed, generator_kind) = level
+        .as_ref()
+        .map_or((settings.seed, settings.generator), |level| {
+            (level.seed, level.generator)
+        });
+
+    let terrain_blocks = TerrainBlocks::from_registry(&registry);
+    let generator: Arc<dyn WorldGenerator> = match generator_kind {
+        GeneratorKind::Noise => Arc::new(NoiseGenerator::new(seed, terrain_blocks)),
+        GeneratorKind::Superflat => Arc::new(SuperflatGenerator::from_blocks(-10, terrain_blocks)),
+    };
+
+    let mut chunk_map = ChunkMap::default();
+
+    let (spawn, time, player) = match level {
+        Some(level) => {
+            info!("loaded world {} with seed {seed}", settings.world);
+
+            (IVec3::from_array(level.spawn), level.time, level.player)
+        }
+        None => {
+            info!("generating world {} with seed {seed}", settings.world);
+
+            // Load the spawn column up front to find the surface, the rest is streamed in.
+            // Imported worlds already have chunks saved without a level.
+            for y in -render_distance.vertical..=render_distance.vertical {
+                let chunk_position = IVec3::new(0, y, 0);
+                let chunk = save
+                    .load_chunk(chunk_position)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| generator.generate(chunk_position));
+
+                chunk_map.insert_chunk(chunk_position, chunk);
+            }
+
+            let top = (render_distance.vertical + 1) * CHUNK_SIZE;
+            let spawn_height = (-top..top)
+                .rev()
+                .find(|&y| !chunk_map.get_block(IVec3::new(0, y, 0)).is_air())
+                .unwrap_or(0);
+
+            let spawn = IVec3::new(0, spawn_height + 2, 0);
+            let player = PlayerData {
+                position: spawn.as_vec3().to_array(),
+                yaw: 0.0,
+                pitch: 0.0,
+            };
+
+            (spawn, 0.0, player)
+        }
+    };
+
+    let time = settings.time_of_day.map_or(time, |time_of_day| {
+        daylight::with_time_of_day(time, time_of_day)
+    });
+
     commands.insert_resource(state);
+    commands.insert_resource(MeshingRegistry(Arc::new(registry.clone())));
+    commands.insert_resource(registry);
+    commands.insert_resource(items);
+    commands.insert_resource(chunk_map);
+    commands.insert_resource(WorldGen(generator));
+    commands.insert_resource(ChunkMaterial(material));
+    commands.insert_resource(WorldTime(time));
+    commands.insert_resource(save);
+    commands.insert_resource(LevelInfo {
+        seed,
+        generator: generator_kind,
+        spawn,
+    });
 
+    // Moved across the sky by the day and night cycle.
     commands.spawn((
+        Sun,
         DirectionalLight {
             color: Color::WHITE,
             shadows_enabled: true,
             ..default()
         },
-        Transform::from_xyz(0.0, 0.0, 0.0).looking_at(Vec3::new(-0.15, -0.05, 0.25), Vec3::Y),
+        Transform::default(),
     ));
 
     commands
         .spawn((
             PlayerBody,
-            WishDir(Vec2::ZERO),
-            Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(1.0, 2.0, 1.0)))),
+            WishDir(Vec2::new(player.yaw, player.pitch)),
+            inventory,
+            CraftingGrid::default(),
+            Health::default(),
+            Hunger::default(),
+            Mesh3d(meshes.add(Cuboid::from_size(PLAYER_SIZE))),
             MeshMaterial3d(materials.add(StandardMaterial {
                 base_color: Color::WHITE,
                 ..default()
             })),
             InputManagerBundle::with_map(Action::input_map()),
-            CharacterControllerBundle::new(Collider::capsule(0.5, 1.0), Vector::NEG_Y * 9.81 * 2.0)
-                .with_movement(30.0, 0.92, 7.0, (30.0 as Scalar).to_radians()),
-            Transform::default(),
+            CharacterControllerBundle::new(PLAYER_SIZE, Vector::NEG_Y * 9.81 * 2.0)
+                .with_movement(30.0, 0.92, 7.0)
+                .with_step_height(1.0),
+            Transform::from_translation(Vec3::from_array(player.position)),
         ))
         .with_children(|builder| {
             builder
@@ -230,6 +620,19 @@ fn finalize(
     });
 }
 
+/// Builds the recipes once the items they use are registered by [`finalize`].
+fn load_recipes(
+    mut commands: Commands,
+    items: Res<ItemRegistry>,
+    recipe_defs: Res<Assets<RecipeDef>>,
+    loaded_folders: Res<Assets<LoadedFolder>>,
+    recipe_folder: Res<RecipeFolder>,
+) {
+    let defs = folder_defs(&loaded_folders, &recipe_folder.0, &recipe_defs);
+
+    commands.insert_resource(RecipeRegistry::new(defs, &items));
+}
+
 fn update_hud(
     velocity: Single<&LinearVelocity, With<PlayerBody>>,
     player_body: Single<(&Transform, &WishDir), With<PlayerBody>>,
@@ -243,43 +646,18 @@ fn update_hud(
     ***text = format!("XYZ: {x:0.2}, {y:0.2}, {z:0.2}\nVEL: {vx:0.2}, {vy:0.2}, {vz:0.2}\n YP: {yaw:0.2}, {pitch:0.2}");
 }
 
-fn spawn_block(commands: &mut Commands, state: &State, position: Vec3) {
-    info!("spawn block at {position:?}");
-
-    commands
-        .spawn((
-            Block,
-            Mesh3d(state.block.clone()),
-            MeshMaterial3d(state.material.clone()),
-            Transform::from_translation(position),
-            RigidBody::Static,
-            Collider::cuboid(1.0, 1.0, 1.0),
-        ))
-        .observe(on_pointer_over)
-        .observe(on_pointer_out)
-        .observe(on_pointer_click);
-}
-
-fn on_pointer_over(trigger: Trigger<Pointer<Over>>, mut commands: Commands) {
-    commands.entity(trigger.entity()).insert(Wireframe);
-}
-
-fn on_pointer_out(trigger: Trigger<Pointer<Out>>, mut commands: Commands) {
-    commands.entity(trigger.entity()).remove::<Wireframe>();
-}
-
-fn on_pointer_click(
-    trigger: Trigger<Pointer<Click>>,
-    mut commands: Commands,
-    query: Query<&Transform, With<Block>>,
-    state: Res<State>,
+/// Moves the selection outline onto the targeted block.
+fn update_selection(
+    target: Res<TargetBlock>,
+    selection: Single<(&mut Transform, &mut Visibility), With<Selection>>,
 ) {
-    let entity = trigger.entity();
-    let position = query.get(entity).unwrap().translation + Vec3::Y;
+    let (mut transform, mut visibility) = selection.into_inner();
 
-    match trigger.event().button {
-        PointerButton::Primary => spawn_block(&mut commands, &state, position),
-        PointerButton::Secondary => commands.entity(entity).despawn_recursive(),
-        _ => {}
+    match **target {
+        Some(hit) => {
+            transform.translation = hit.block.as_vec3();
+            *visibility = Visibility::Visible;
+        }
+        None => *visibility = Visibility::Hidden,
     }
 }
@@ -0,0 +1,24 @@
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef};
+
+/// The material used by block meshes.
+pub type BlockMaterial = ExtendedMaterial<StandardMaterial, BlockAtlas>;
+
+/// Samples block faces from the texture atlas, repeating a tile across faces
+/// that span multiple blocks. See `assets/shaders/block.wgsl`.
+#[derive(Asset, AsBindGroup, Clone, Debug, Reflect)]
+pub struct BlockAtlas {
+    /// The size of a single tile, in UV coordinates.
+    #[uniform(100)]
+    pub tile_size: Vec2,
+    #[texture(101)]
+    #[sampler(102)]
+    pub texture: Handle<Image>,
+}
+
+impl MaterialExtension for BlockAtlas {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+}
@@ -1,93 +1,442 @@
+use crate::world::{chunk_origin, BlockId, ChunkMap, CHUNK_SIZE};
 use bevy::prelude::*;
 use bevy::render::mesh::{Indices, PrimitiveTopology};
 
-const TEXTURE_SIZE: f32 = 16.0;
+/// The width and height of a block texture, in pixels.
+pub const TEXTURE_SIZE: f32 = 16.0;
 
-fn block_uv(index: u32) -> [Vec2; 2] {
-    let position = index as f32 * Vec2::X;
+/// How chunk meshes are built.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Resource)]
+pub enum MeshingMode {
+    /// One quad per visible block face.
+    Culled,
+    /// Visible faces sharing a plane and texture are merged into larger quads.
+    #[default]
+    Greedy,
+}
+
+/// Describes the faces of a type of block to the mesher.
+pub trait BlockFaces {
+    /// The atlas index of each face, in the order front, back, right, left, top, bottom.
+    fn textures(&self, block: BlockId) -> [u32; 6];
+
+    /// Whether the faces of neighbouring blocks can be seen through this block.
+    fn is_transparent(&self, block: BlockId) -> bool;
+
+    /// The minimum corner of a texture within the atlas, in UV coordinates.
+    fn texture_uv(&self, texture: u32) -> Vec2;
+}
+
+/// A face of a unit cube centered on the origin.
+struct Face {
+    normal: IVec3,
+    /// The axes along which the face's U and V coordinates run.
+    axes: [usize; 2],
+    /// Each corner's position, and where it lies between the minimum and maximum of the face's UV.
+    corners: [(Vec3, Vec2); 4],
+}
+
+const MIN: f32 = -0.5;
+const MAX: f32 = 0.5;
+
+const FACES: [Face; 6] = [
+    // Front
+    Face {
+        normal: IVec3::Z,
+        axes: [0, 1],
+        corners: [
+            (Vec3::new(MIN, MIN, MAX), Vec2::new(0.0, 1.0)),
+            (Vec3::new(MAX, MIN, MAX), Vec2::new(1.0, 1.0)),
+            (Vec3::new(MAX, MAX, MAX), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MIN, MAX, MAX), Vec2::new(0.0, 0.0)),
+        ],
+    },
+    // Back
+    Face {
+        normal: IVec3::NEG_Z,
+        axes: [0, 1],
+        corners: [
+            (Vec3::new(MIN, MAX, MIN), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MAX, MAX, MIN), Vec2::new(0.0, 0.0)),
+            (Vec3::new(MAX, MIN, MIN), Vec2::new(0.0, 1.0)),
+            (Vec3::new(MIN, MIN, MIN), Vec2::new(1.0, 1.0)),
+        ],
+    },
+    // Right
+    Face {
+        normal: IVec3::X,
+        axes: [2, 1],
+        corners: [
+            (Vec3::new(MAX, MIN, MIN), Vec2::new(1.0, 1.0)),
+            (Vec3::new(MAX, MAX, MIN), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MAX, MAX, MAX), Vec2::new(0.0, 0.0)),
+            (Vec3::new(MAX, MIN, MAX), Vec2::new(0.0, 1.0)),
+        ],
+    },
+    // Left
+    Face {
+        normal: IVec3::NEG_X,
+        axes: [2, 1],
+        corners: [
+            (Vec3::new(MIN, MIN, MAX), Vec2::new(1.0, 1.0)),
+            (Vec3::new(MIN, MAX, MAX), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MIN, MAX, MIN), Vec2::new(0.0, 0.0)),
+            (Vec3::new(MIN, MIN, MIN), Vec2::new(0.0, 1.0)),
+        ],
+    },
+    // Top
+    Face {
+        normal: IVec3::Y,
+        axes: [0, 2],
+        corners: [
+            (Vec3::new(MAX, MAX, MIN), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MIN, MAX, MIN), Vec2::new(0.0, 0.0)),
+            (Vec3::new(MIN, MAX, MAX), Vec2::new(0.0, 1.0)),
+            (Vec3::new(MAX, MAX, MAX), Vec2::new(1.0, 1.0)),
+        ],
+    },
+    // Bottom
+    Face {
+        normal: IVec3::NEG_Y,
+        axes: [0, 2],
+        corners: [
+            (Vec3::new(MAX, MIN, MAX), Vec2::new(1.0, 1.0)),
+            (Vec3::new(MIN, MIN, MAX), Vec2::new(1.0, 0.0)),
+            (Vec3::new(MIN, MIN, MIN), Vec2::new(0.0, 0.0)),
+            (Vec3::new(MAX, MIN, MIN), Vec2::new(0.0, 1.0)),
+        ],
+    },
+];
+
+/// Vertex data accumulated while building a mesh.
+#[derive(Default)]
+struct MeshBuffers {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    tiles: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuffers {
+    /// Pushes a face covering the blocks from `start` to `start + size - 1`.
+    ///
+    /// The UV runs from zero to the size of the face in blocks, so that the shader
+    /// repeats the tile once per block.
+    fn push_face(&mut self, face: &Face, start: IVec3, size: IVec3, tile: Vec2) {
+        let size = size.as_vec3();
+        let center = start.as_vec3() + (size - Vec3::ONE) / 2.0;
+        let uv_size = Vec2::new(size[face.axes[0]], size[face.axes[1]]);
+        let start = self.positions.len() as u32;
+
+        for (corner, uv) in face.corners {
+            self.positions.push((center + corner * size).into());
+            self.normals.push(face.normal.as_vec3().into());
+            self.uvs.push((uv * uv_size).into());
+            self.tiles.push(tile.into());
+        }
+
+        self.indices
+            .extend([0, 1, 2, 2, 3, 0].map(|index| start + index));
+    }
+
+    fn into_mesh(self) -> Mesh {
+        Mesh::new(PrimitiveTopology::TriangleList, default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_1, self.tiles)
+            .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+/// Builds the mesh of a single block, centered on the origin.
+pub fn new_block(blocks: &impl BlockFaces, block: BlockId) -> Mesh {
+    let mut buffers = MeshBuffers::default();
+
+    for (face, texture) in FACES.iter().zip(blocks.textures(block)) {
+        buffers.push_face(face, IVec3::ZERO, IVec3::ONE, blocks.texture_uv(texture));
+    }
+
+    buffers.into_mesh()
+}
+
+/// Builds the mesh of a flat item, the front and back faces of a block centered on the
+/// origin, both showing `texture`.
+pub fn new_item(blocks: &impl BlockFaces, texture: u32) -> Mesh {
+    let mut buffers = MeshBuffers::default();
+
+    for face in &FACES[..2] {
+        buffers.push_face(face, IVec3::ZERO, IVec3::ONE, blocks.texture_uv(texture));
+    }
+
+    buffers.into_mesh()
+}
+
+/// Builds the mesh of a chunk, relative to its origin.
+///
+/// Only faces that border air or a transparent block are emitted, including faces
+/// bordering blocks in neighbouring chunks. Returns `None` if no faces are visible.
+pub fn new_chunk(
+    chunk_map: &ChunkMap,
+    chunk_position: IVec3,
+    blocks: &impl BlockFaces,
+    mode: MeshingMode,
+) -> Option<Mesh> {
+    let chunk = chunk_map.chunk(chunk_position)?;
+    let origin = chunk_origin(chunk_position);
+    let mut buffers = MeshBuffers::default();
+
+    // The texture of a face, if it is visible.
+    let visible_face = |local: IVec3, face: usize| {
+        let block = chunk.get(local);
+
+        if block.is_air() {
+            return None;
+        }
+
+        let neighbour = local + FACES[face].normal;
+        let neighbour = if is_local(neighbour) {
+            chunk.get(neighbour)
+        } else {
+            chunk_map.get_block(origin + neighbour)
+        };
+
+        is_face_visible(blocks, block, neighbour).then(|| blocks.textures(block)[face])
+    };
+
+    match mode {
+        MeshingMode::Culled => {
+            for (local, _block) in chunk.iter() {
+                for (index, face) in FACES.iter().enumerate() {
+                    if let Some(texture) = visible_face(local, index) {
+                        let tile = blocks.texture_uv(texture);
+
+                        buffers.push_face(face, local, IVec3::ONE, tile);
+                    }
+                }
+            }
+        }
+        MeshingMode::Greedy => {
+            for (index, face) in FACES.iter().enumerate() {
+                greedy_face(&mut buffers, face, blocks, |local| {
+                    visible_face(local, index)
+                });
+            }
+        }
+    }
+
+    (!buffers.indices.is_empty()).then(|| buffers.into_mesh())
+}
+
+/// Merges the visible faces facing one direction into as few quads as possible,
+/// one slice of the chunk at a time.
+fn greedy_face(
+    buffers: &mut MeshBuffers,
+    face: &Face,
+    blocks: &impl BlockFaces,
+    visible_face: impl Fn(IVec3) -> Option<u32>,
+) {
+    const SIZE: usize = CHUNK_SIZE as usize;
+
+    let [u_axis, v_axis] = face.axes;
+    let depth_axis = face
+        .normal
+        .abs()
+        .to_array()
+        .iter()
+        .position(|&n| n != 0)
+        .unwrap();
+
+    let position = |depth: usize, u: usize, v: usize| {
+        let mut position = IVec3::ZERO;
+
+        position[depth_axis] = depth as i32;
+        position[u_axis] = u as i32;
+        position[v_axis] = v as i32;
+        position
+    };
+
+    for depth in 0..SIZE {
+        let mut mask = [[None; SIZE]; SIZE];
+
+        for (u, column) in mask.iter_mut().enumerate() {
+            for (v, texture) in column.iter_mut().enumerate() {
+                *texture = visible_face(position(depth, u, v));
+            }
+        }
 
-    let min = position / TEXTURE_SIZE;
-    let max = (position + Vec2::ONE) / TEXTURE_SIZE;
+        for u in 0..SIZE {
+            let mut v = 0;
 
-    [min, max]
+            while v < SIZE {
+                let Some(texture) = mask[u][v] else {
+                    v += 1;
+                    continue;
+                };
+
+                // Grow along V first, then along U while every row matches.
+                let height = (v..SIZE)
+                    .take_while(|&v| mask[u][v] == Some(texture))
+                    .count();
+
+                let width = (u..SIZE)
+                    .take_while(|&u| mask[u][v..v + height].iter().all(|&t| t == Some(texture)))
+                    .count();
+
+                for column in &mut mask[u..u + width] {
+                    column[v..v + height].fill(None);
+                }
+
+                let size = position(1, width, height);
+
+                let tile = blocks.texture_uv(texture);
+
+                buffers.push_face(face, position(depth, u, v), size, tile);
+
+                v += height;
+            }
+        }
+    }
+}
+
+fn is_local(local: IVec3) -> bool {
+    local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNK_SIZE)).all()
+}
+
+/// Whether the face of `block` touching `neighbour` can be seen.
+fn is_face_visible(blocks: &impl BlockFaces, block: BlockId, neighbour: BlockId) -> bool {
+    neighbour.is_air() || (neighbour != block && blocks.is_transparent(neighbour))
 }
 
-pub fn new_block(front: u32, back: u32, right: u32, left: u32, top: u32, bottom: u32) -> Mesh {
-    let [front_min, front_max] = block_uv(front);
-    let [back_min, back_max] = block_uv(back);
-    let [right_min, right_max] = block_uv(right);
-    let [left_min, left_max] = block_uv(left);
-    let [top_min, top_max] = block_uv(top);
-    let [bottom_min, bottom_max] = block_uv(bottom);
-
-    let min = -0.5;
-    let max = 0.5;
-
-    let vertices = &[
-        // Front
-        ([min, min, max], [0.0, 0.0, 1.0], [front_min.x, front_max.y]),
-        ([max, min, max], [0.0, 0.0, 1.0], [front_max.x, front_max.y]),
-        ([max, max, max], [0.0, 0.0, 1.0], [front_max.x, front_min.y]),
-        ([min, max, max], [0.0, 0.0, 1.0], [front_min.x, front_min.y]),
-        // Back
-        ([min, max, min], [0.0, 0.0, -1.0], [back_max.x, back_min.y]),
-        ([max, max, min], [0.0, 0.0, -1.0], [back_min.x, back_min.y]),
-        ([max, min, min], [0.0, 0.0, -1.0], [back_min.x, back_max.y]),
-        ([min, min, min], [0.0, 0.0, -1.0], [back_max.x, back_max.y]),
-        // Right
-        ([max, min, min], [1.0, 0.0, 0.0], [right_max.x, right_max.y]),
-        ([max, max, min], [1.0, 0.0, 0.0], [right_max.x, right_min.y]),
-        ([max, max, max], [1.0, 0.0, 0.0], [right_min.x, right_min.y]),
-        ([max, min, max], [1.0, 0.0, 0.0], [right_min.x, right_max.y]),
-        // Left
-        ([min, min, max], [-1.0, 0.0, 0.0], [left_max.x, left_max.y]),
-        ([min, max, max], [-1.0, 0.0, 0.0], [left_max.x, left_min.y]),
-        ([min, max, min], [-1.0, 0.0, 0.0], [left_min.x, left_min.y]),
-        ([min, min, min], [-1.0, 0.0, 0.0], [left_min.x, left_max.y]),
-        // Top
-        ([max, max, min], [0.0, 1.0, 0.0], [top_max.x, top_min.y]),
-        ([min, max, min], [0.0, 1.0, 0.0], [top_min.x, top_min.y]),
-        ([min, max, max], [0.0, 1.0, 0.0], [top_min.x, top_max.y]),
-        ([max, max, max], [0.0, 1.0, 0.0], [top_max.x, top_max.y]),
-        // Bottom
-        (
-            [max, min, max],
-            [0.0, -1.0, 0.0],
-            [bottom_max.x, bottom_max.y],
-        ),
-        (
-            [min, min, max],
-            [0.0, -1.0, 0.0],
-            [bottom_max.x, bottom_min.y],
-        ),
-        (
-            [min, min, min],
-            [0.0, -1.0, 0.0],
-            [bottom_min.x, bottom_min.y],
-        ),
-        (
-            [max, min, min],
-            [0.0, -1.0, 0.0],
-            [bottom_min.x, bottom_max.y],
-        ),
-    ];
-
-    let positions: Vec<_> = vertices.iter().map(|(p, _, _)| *p).collect();
-    let normals: Vec<_> = vertices.iter().map(|(_, n, _)| *n).collect();
-    let uvs: Vec<_> = vertices.iter().map(|(_, _, uv)| *uv).collect();
-
-    let indices = Indices::U32(vec![
-        0, 1, 2, 2, 3, 0, // Front
-        4, 5, 6, 6, 7, 4, // Back
-        8, 9, 10, 10, 11, 8, // Right
-        12, 13, 14, 14, 15, 12, // Left
-        16, 17, 18, 18, 19, 16, // Top
-        20, 21, 22, 22, 23, 20, // Bottom
-    ]);
-
-    Mesh::new(PrimitiveTopology::TriangleList, default())
-        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
-        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
-        .with_inserted_indices(indices)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::mesh::{MeshVertexAttribute, VertexAttributeValues};
+    use std::collections::HashMap;
+
+    const STONE: BlockId = BlockId(1);
+    const DIRT: BlockId = BlockId(2);
+
+    /// Opaque blocks with one texture per block, named after their id.
+    struct TestBlocks;
+
+    impl BlockFaces for TestBlocks {
+        fn textures(&self, block: BlockId) -> [u32; 6] {
+            [block.0 as u32; 6]
+        }
+
+        fn is_transparent(&self, block: BlockId) -> bool {
+            block.is_air()
+        }
+
+        fn texture_uv(&self, _texture: u32) -> Vec2 {
+            Vec2::ZERO
+        }
+    }
+
+    fn float3(mesh: &Mesh, attribute: MeshVertexAttribute) -> &[[f32; 3]] {
+        mesh.attribute(attribute)
+            .and_then(VertexAttributeValues::as_float3)
+            .unwrap()
+    }
+
+    fn quads(mesh: &Mesh) -> usize {
+        float3(mesh, Mesh::ATTRIBUTE_POSITION).len() / 4
+    }
+
+    /// The total area of the faces facing each way.
+    fn face_areas(mesh: &Mesh) -> HashMap<IVec3, f32> {
+        let positions = float3(mesh, Mesh::ATTRIBUTE_POSITION);
+        let normals = float3(mesh, Mesh::ATTRIBUTE_NORMAL);
+        let mut areas = HashMap::new();
+
+        for (corners, normal) in positions.chunks_exact(4).zip(normals.iter().step_by(4)) {
+            let [a, b, _, d] = [0, 1, 2, 3].map(|index| Vec3::from(corners[index]));
+
+            *areas.entry(Vec3::from(*normal).as_ivec3()).or_default() +=
+                (b - a).cross(d - a).length();
+        }
+
+        areas
+    }
+
+    fn slab() -> ChunkMap {
+        let mut chunk_map = ChunkMap::default();
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk_map.set_block(IVec3::new(x, 0, z), STONE);
+            }
+        }
+
+        chunk_map
+    }
+
+    #[test]
+    fn greedy_merges_a_slab() {
+        let chunk_map = slab();
+        let culled = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, MeshingMode::Culled).unwrap();
+        let greedy = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, MeshingMode::Greedy).unwrap();
+
+        // A quad per face of every block on the top and bottom, and along the edges.
+        assert_eq!(quads(&culled), 2 * 16 * 16 + 4 * 16);
+        assert_eq!(quads(&greedy), 6);
+    }
+
+    #[test]
+    fn both_modes_cover_the_same_area() {
+        let mut chunk_map = slab();
+
+        // Some bumps of different blocks, so that not everything merges.
+        for (position, block) in [
+            (IVec3::new(3, 1, 3), DIRT),
+            (IVec3::new(4, 1, 3), DIRT),
+            (IVec3::new(4, 2, 3), STONE),
+            (IVec3::new(10, 1, 12), STONE),
+            (IVec3::new(15, 1, 15), DIRT),
+            (IVec3::new(8, 0, 8), DIRT),
+        ] {
+            chunk_map.set_block(position, block);
+        }
+
+        let culled = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, MeshingMode::Culled).unwrap();
+        let greedy = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, MeshingMode::Greedy).unwrap();
+        let culled_areas = face_areas(&culled);
+        let greedy_areas = face_areas(&greedy);
+
+        assert_eq!(culled_areas.len(), 6);
+
+        for (normal, area) in culled_areas {
+            assert!(
+                (greedy_areas[&normal] - area).abs() < 1e-3,
+                "{normal}: {} != {area}",
+                greedy_areas[&normal]
+            );
+        }
+    }
+
+    #[test]
+    fn faces_against_neighbour_chunks_are_culled() {
+        let mut chunk_map = ChunkMap::default();
+
+        chunk_map.set_block(IVec3::new(15, 0, 0), STONE);
+
+        for mode in [MeshingMode::Culled, MeshingMode::Greedy] {
+            let alone = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, mode).unwrap();
+
+            assert!(face_areas(&alone).contains_key(&IVec3::X), "{mode:?}");
+        }
+
+        // The block across the border, in the next chunk.
+        chunk_map.set_block(IVec3::new(16, 0, 0), STONE);
+
+        for mode in [MeshingMode::Culled, MeshingMode::Greedy] {
+            let covered = new_chunk(&chunk_map, IVec3::ZERO, &TestBlocks, mode).unwrap();
+            let areas = face_areas(&covered);
+
+            assert!(!areas.contains_key(&IVec3::X), "{mode:?}");
+            assert_eq!(areas.len(), 5, "{mode:?}");
+        }
+    }
 }
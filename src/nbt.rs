@@ -0,0 +1,364 @@
+//! Minecraft's Named Binary Tag format, as used by Java Edition worlds and schematics.
+//!
+//! Everything is big endian, and strings are treated as UTF-8.
+
+use std::collections::BTreeMap;
+use std::io;
+
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// How deeply lists and compounds may nest, so that malicious files can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Self::Byte(_) => BYTE,
+            Self::Short(_) => SHORT,
+            Self::Int(_) => INT,
+            Self::Long(_) => LONG,
+            Self::Float(_) => FLOAT,
+            Self::Double(_) => DOUBLE,
+            Self::ByteArray(_) => BYTE_ARRAY,
+            Self::String(_) => STRING,
+            Self::List(_) => LIST,
+            Self::Compound(_) => COMPOUND,
+            Self::IntArray(_) => INT_ARRAY,
+            Self::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    /// The value of any integer tag, widened to an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::Byte(value) => Some(value.into()),
+            Self::Short(value) => Some(value.into()),
+            Self::Int(value) => Some(value.into()),
+            Self::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Self::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Self::Compound(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Self::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Self::IntArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Self::LongArray(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take_slice(N)?.try_into().unwrap())
+    }
+
+    fn take_slice(&mut self, len: usize) -> io::Result<&[u8]> {
+        if self.bytes.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn read_len(&mut self) -> io::Result<usize> {
+        let len = i32::from_be_bytes(self.take()?);
+
+        usize::try_from(len).map_err(|_| invalid_data("negative length"))
+    }
+
+    fn read_string(&mut self) -> io::Result<String> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+
+        Ok(String::from_utf8_lossy(self.take_slice(len)?).into_owned())
+    }
+
+    fn read_array<T, const N: usize>(
+        &mut self,
+        from_be_bytes: fn([u8; N]) -> T,
+    ) -> io::Result<Vec<T>> {
+        let len = self.read_len()?;
+        let bytes = self.take_slice(len.checked_mul(N).ok_or(io::ErrorKind::InvalidData)?)?;
+
+        Ok(bytes
+            .chunks_exact(N)
+            .map(|bytes| from_be_bytes(bytes.try_into().unwrap()))
+            .collect())
+    }
+
+    fn read_payload(&mut self, id: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("tags are nested too deeply"));
+        }
+
+        let tag = match id {
+            BYTE => Tag::Byte(i8::from_be_bytes(self.take()?)),
+            SHORT => Tag::Short(i16::from_be_bytes(self.take()?)),
+            INT => Tag::Int(i32::from_be_bytes(self.take()?)),
+            LONG => Tag::Long(i64::from_be_bytes(self.take()?)),
+            FLOAT => Tag::Float(f32::from_be_bytes(self.take()?)),
+            DOUBLE => Tag::Double(f64::from_be_bytes(self.take()?)),
+            BYTE_ARRAY => Tag::ByteArray(self.read_array(i8::from_be_bytes)?),
+            STRING => Tag::String(self.read_string()?),
+            LIST => {
+                let [element_id] = self.take()?;
+                let len = self.read_len()?;
+                let mut list = Vec::new();
+
+                for _ in 0..len {
+                    list.push(self.read_payload(element_id, depth + 1)?);
+                }
+
+                Tag::List(list)
+            }
+            COMPOUND => Tag::Compound(self.read_compound(depth + 1)?),
+            INT_ARRAY => Tag::IntArray(self.read_array(i32::from_be_bytes)?),
+            LONG_ARRAY => Tag::LongArray(self.read_array(i64::from_be_bytes)?),
+            _ => return Err(invalid_data("unknown tag type")),
+        };
+
+        Ok(tag)
+    }
+
+    fn read_compound(&mut self, depth: usize) -> io::Result<Compound> {
+        let mut compound = Compound::new();
+
+        loop {
+            let [id] = self.take()?;
+
+            if id == END {
+                return Ok(compound);
+            }
+
+            let name = self.read_string()?;
+            let tag = self.read_payload(id, depth)?;
+
+            compound.insert(name, tag);
+        }
+    }
+}
+
+/// Reads an uncompressed NBT file, returning the name and contents of the root compound.
+pub fn read(bytes: &[u8]) -> io::Result<(String, Compound)> {
+    let mut reader = Reader { bytes };
+    let [id] = reader.take()?;
+
+    if id != COMPOUND {
+        return Err(invalid_data("root tag is not a compound"));
+    }
+
+    let name = reader.read_string()?;
+    let root = reader.read_compound(0)?;
+
+    Ok((name, root))
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    let value = &value.as_bytes()[..value.len().min(u16::MAX as usize)];
+
+    bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(value);
+}
+
+fn write_payload(bytes: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Short(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            bytes.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        }
+        Tag::String(value) => write_string(bytes, value),
+        Tag::List(values) => {
+            bytes.push(values.first().map_or(END, Tag::id));
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+
+            for value in values {
+                write_payload(bytes, value);
+            }
+        }
+        Tag::Compound(compound) => write_compound(bytes, compound),
+        Tag::IntArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            bytes.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        }
+        Tag::LongArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            bytes.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        }
+    }
+}
+
+fn write_compound(bytes: &mut Vec<u8>, compound: &Compound) {
+    for (name, tag) in compound {
+        bytes.push(tag.id());
+        write_string(bytes, name);
+        write_payload(bytes, tag);
+    }
+
+    bytes.push(END);
+}
+
+/// Writes an uncompressed NBT file with `root` as the root compound.
+pub fn write(name: &str, root: &Compound) -> Vec<u8> {
+    let mut bytes = vec![COMPOUND];
+
+    write_string(&mut bytes, name);
+    write_compound(&mut bytes, root);
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_tag_round_trips() {
+        let nested = Compound::from([
+            ("name".to_string(), Tag::String("stone".to_string())),
+            ("count".to_string(), Tag::Byte(64)),
+        ]);
+        let root = Compound::from([
+            ("byte".to_string(), Tag::Byte(-12)),
+            ("short".to_string(), Tag::Short(-1234)),
+            ("int".to_string(), Tag::Int(123_456_789)),
+            ("long".to_string(), Tag::Long(-1 << 40)),
+            ("float".to_string(), Tag::Float(1.5)),
+            ("double".to_string(), Tag::Double(-0.25)),
+            ("byte_array".to_string(), Tag::ByteArray(vec![1, -2, 3])),
+            ("string".to_string(), Tag::String("héllo".to_string())),
+            (
+                "list".to_string(),
+                Tag::List(vec![Tag::Short(1), Tag::Short(2)]),
+            ),
+            ("empty_list".to_string(), Tag::List(Vec::new())),
+            (
+                "compound_list".to_string(),
+                Tag::List(vec![
+                    Tag::Compound(nested.clone()),
+                    Tag::Compound(Compound::new()),
+                ]),
+            ),
+            ("compound".to_string(), Tag::Compound(nested)),
+            (
+                "int_array".to_string(),
+                Tag::IntArray(vec![i32::MIN, 0, i32::MAX]),
+            ),
+            (
+                "long_array".to_string(),
+                Tag::LongArray(vec![i64::MIN, 0, i64::MAX]),
+            ),
+        ]);
+
+        let (name, read_root) = read(&write("Schematic", &root)).unwrap();
+
+        assert_eq!(name, "Schematic");
+        assert_eq!(read_root, root);
+    }
+
+    /// A compound holding compounds `depth` deep.
+    fn nested(depth: usize) -> Compound {
+        let mut compound = Compound::new();
+
+        for _ in 0..depth {
+            compound = Compound::from([("inner".to_string(), Tag::Compound(compound))]);
+        }
+
+        compound
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        assert!(read(&write("", &nested(MAX_DEPTH / 2))).is_ok());
+        assert!(read(&write("", &nested(MAX_DEPTH + 2))).is_err());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let root = Compound::from([("long".to_string(), Tag::Long(7))]);
+        let bytes = write("", &root);
+
+        assert!(read(&bytes[..bytes.len() - 2]).is_err());
+    }
+}
@@ -1,38 +1,82 @@
-// pasted from https://github.com/Jondolf/avian/blob/main/crates/avian3d/examples/kinematic_character_3d/plugin.rs
+// started out pasted from https://github.com/Jondolf/avian/blob/main/crates/avian3d/examples/kinematic_character_3d/plugin.rs
 // but with leafwing input stuff, and the player updating stuff
-// likely to change in the future
+// collisions are resolved against the voxel grid rather than by avian
 
 use super::Action;
-use crate::{PlayerBody, PlayerEye, WishDir};
+use crate::block::BlockRegistry;
+use crate::inventory_screen::InventoryScreen;
+use crate::world::ChunkMap;
+use crate::{GameState, PlayerBody, PlayerEye, WishDir};
 use avian3d::math::*;
 use avian3d::prelude::*;
+use bevy::app::RunFixedMainLoopSystem;
+use bevy::ecs::component::ComponentId;
 use bevy::ecs::query::Has;
+use bevy::ecs::world::DeferredWorld;
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
 
+/// How many times per second character controllers are simulated.
+const TICK_RATE: f64 = 60.0;
+
+/// How much of the usual acceleration sneaking character controllers move with.
+const SNEAK_ACCELERATION_FACTOR: Scalar = 0.3;
+
+/// How much faster than usual sprinting character controllers accelerate.
+const SPRINT_ACCELERATION_FACTOR: Scalar = 1.3;
+
+/// How quickly forward has to be pressed twice to start sprinting, in seconds.
+const DOUBLE_TAP_TIME: f32 = 0.3;
+
+/// The field of view of the player's camera, widened by [`SPRINT_FOV_FACTOR`] while sprinting.
+const FOV: f32 = FRAC_PI_4;
+const SPRINT_FOV_FACTOR: f32 = 1.15;
+
+/// How far apart faces can be and still count as touching, to avoid floating point trouble.
+const EPSILON: Scalar = 0.001;
+
+/// How far below the feet of a sneaking character controller ground counts as support.
+const EDGE_SUPPORT_DEPTH: Scalar = 0.25;
+
 pub struct CharacterControllerPlugin;
 
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
+        // Movement is simulated on a fixed tick so that it doesn't depend on the frame rate,
+        // and shown interpolated between the last two ticks.
         app.add_event::<MovementAction>()
+            .add_event::<Stepped>()
+            .add_event::<Landed>()
+            .add_event::<Jumped>()
+            .insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
-                    (input, (player_body, player_eye)).chain(),
-                    update_grounded,
+                    store_previous_translation,
+                    input,
                     apply_gravity,
+                    sneak,
+                    sprint,
                     movement,
                     apply_movement_damping,
+                    stop_at_edges,
+                    move_and_slide,
                 )
-                    .chain(),
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
             )
             .add_systems(
-                // Run collision handling after collision detection.
-                //
-                // NOTE: The collision implementation here is very basic and a bit buggy.
-                //       A collide-and-slide algorithm would likely work better.
-                PostProcessCollisions,
-                kinematic_controller_collisions,
+                RunFixedMainLoop,
+                interpolate_translation.in_set(RunFixedMainLoopSystem::AfterFixedMainLoop),
+            )
+            .add_systems(
+                Update,
+                (
+                    look.run_if(in_state(GameState::InGame)),
+                    (player_body, player_eye, player_fov),
+                    smooth_player_eye_steps,
+                )
+                    .chain(),
             );
     }
 }
@@ -42,17 +86,145 @@ impl Plugin for CharacterControllerPlugin {
 pub enum MovementAction {
     Move(Vector2),
     Jump,
+    /// Whether sneaking is held.
+    Sneak(bool),
+    /// Starts sprinting, or stops it when no longer moving forward.
+    Sprint(bool),
+}
+
+/// Sent when a character controller steps up onto a ledge.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct Stepped {
+    pub entity: Entity,
+    /// How far the character stepped up.
+    pub height: Scalar,
+}
+
+/// Sent when a character controller lands on a block after being in the air.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct Landed {
+    pub entity: Entity,
+    /// How far the character would have had to fall to land this fast, in blocks.
+    pub fall_height: Scalar,
+}
+
+/// Sent when a character controller jumps off the ground.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct Jumped {
+    pub entity: Entity,
+}
+
+/// The collision layers of avian physics entities, dropped items only collide with the world.
+///
+/// Character controllers aren't avian bodies, they collide with the voxel grid in
+/// [`move_and_slide`].
+#[derive(Default, PhysicsLayer)]
+pub enum GameLayer {
+    #[default]
+    World,
+    Item,
 }
 
 /// A marker component indicating that an entity is using a character controller.
 #[derive(Component)]
 pub struct CharacterController;
 
+/// The box a character controller collides with blocks as.
+#[derive(Clone, Copy, Component, Debug)]
+pub struct CharacterBox {
+    pub half_extents: Vector,
+    /// The center of the box, relative to the translation of the character.
+    pub offset: Vector,
+}
+
+impl CharacterBox {
+    pub fn new(size: Vector) -> Self {
+        Self {
+            half_extents: size / 2.0,
+            offset: Vector::ZERO,
+        }
+    }
+
+    /// The bounding box of a character at `translation`.
+    pub fn aabb(&self, translation: Vector) -> ColliderAabb {
+        let center = translation + self.offset;
+
+        ColliderAabb {
+            min: center - self.half_extents,
+            max: center + self.half_extents,
+        }
+    }
+
+    /// The box shrunk by `height` from the top.
+    fn shrunk(&self, height: Scalar) -> Self {
+        Self {
+            half_extents: self.half_extents - Vector::Y * height / 2.0,
+            offset: self.offset - Vector::Y * height / 2.0,
+        }
+    }
+}
+
+/// The translation of a character controller as of the latest fixed tick and the tick before,
+/// its `Transform` is interpolated between the two every frame.
+///
+/// Starts out at the `Transform` of the entity, move the character by setting both.
+#[derive(Clone, Copy, Component, Debug, Default)]
+#[component(on_add = start_at_transform)]
+pub struct TickTranslation {
+    pub current: Vector,
+    pub previous: Vector,
+}
+
+impl TickTranslation {
+    pub fn new(translation: Vector) -> Self {
+        Self {
+            current: translation,
+            previous: translation,
+        }
+    }
+}
+
+fn start_at_transform(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    let Some(translation) = world
+        .get::<Transform>(entity)
+        .map(|transform| transform.translation)
+    else {
+        return;
+    };
+
+    if let Some(mut tick_translation) = world.get_mut::<TickTranslation>(entity) {
+        *tick_translation = TickTranslation::new(translation);
+    }
+}
+
 /// A marker component indicating that an entity is on the ground.
 #[derive(Component)]
 #[component(storage = "SparseSet")]
 pub struct Grounded;
 
+/// A marker component indicating that a character controller is sneaking.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Sneaking;
+
+/// A marker component indicating that a character controller is sprinting.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Sprinting;
+
+/// How much shorter a character controller is while sneaking, its box shrinks from the top
+/// and the [`PlayerEye`] is lowered by this much.
+#[derive(Component)]
+pub struct SneakHeight(Scalar);
+
+/// The box of a character controller while it isn't sneaking.
+#[derive(Component)]
+pub struct StandingBox(CharacterBox);
+
+/// How high a ledge a character controller can walk onto without jumping.
+#[derive(Component)]
+pub struct StepHeight(Scalar);
+
 /// The acceleration used for character movement.
 #[derive(Component)]
 pub struct MovementAcceleration(Scalar);
@@ -69,20 +241,17 @@ pub struct JumpImpulse(Scalar);
 #[derive(Component)]
 pub struct ControllerGravity(Vector);
 
-/// The maximum angle a slope can have for a character controller
-/// to be able to climb and jump. If the slope is steeper than this angle,
-/// the character will slide down.
-#[derive(Component)]
-pub struct MaxSlopeAngle(Scalar);
-
 /// A bundle that contains the components needed for a basic
 /// kinematic character controller.
 #[derive(Bundle)]
 pub struct CharacterControllerBundle {
     character_controller: CharacterController,
-    rigid_body: RigidBody,
-    collider: Collider,
-    ground_caster: ShapeCaster,
+    character_box: CharacterBox,
+    standing_box: StandingBox,
+    sneak_height: SneakHeight,
+    step_height: StepHeight,
+    tick_translation: TickTranslation,
+    linear_velocity: LinearVelocity,
     gravity: ControllerGravity,
     movement: MovementBundle,
 }
@@ -93,48 +262,37 @@ pub struct MovementBundle {
     acceleration: MovementAcceleration,
     damping: MovementDampingFactor,
     jump_impulse: JumpImpulse,
-    max_slope_angle: MaxSlopeAngle,
 }
 
 impl MovementBundle {
-    pub const fn new(
-        acceleration: Scalar,
-        damping: Scalar,
-        jump_impulse: Scalar,
-        max_slope_angle: Scalar,
-    ) -> Self {
+    pub const fn new(acceleration: Scalar, damping: Scalar, jump_impulse: Scalar) -> Self {
         Self {
             acceleration: MovementAcceleration(acceleration),
             damping: MovementDampingFactor(damping),
             jump_impulse: JumpImpulse(jump_impulse),
-            max_slope_angle: MaxSlopeAngle(max_slope_angle),
         }
     }
 }
 
 impl Default for MovementBundle {
     fn default() -> Self {
-        Self::new(30.0, 0.9, 7.0, PI * 0.45)
+        Self::new(30.0, 0.9, 7.0)
     }
 }
 
 impl CharacterControllerBundle {
-    pub fn new(collider: Collider, gravity: Vector) -> Self {
-        let mut caster_shape = collider.clone();
-
-        caster_shape.set_scale(Vector::ONE * 0.99, 10);
+    /// A character controller with a box of `size` centered on its translation.
+    pub fn new(size: Vector, gravity: Vector) -> Self {
+        let character_box = CharacterBox::new(size);
 
         Self {
             character_controller: CharacterController,
-            rigid_body: RigidBody::Kinematic,
-            collider,
-            ground_caster: ShapeCaster::new(
-                caster_shape,
-                Vector::ZERO,
-                Quaternion::default(),
-                Dir3::NEG_Y,
-            )
-            .with_max_distance(0.2),
+            character_box,
+            standing_box: StandingBox(character_box),
+            sneak_height: SneakHeight(0.3),
+            step_height: StepHeight(0.6),
+            tick_translation: TickTranslation::default(),
+            linear_velocity: LinearVelocity::default(),
             gravity: ControllerGravity(gravity),
             movement: MovementBundle::default(),
         }
@@ -145,35 +303,176 @@ impl CharacterControllerBundle {
         acceleration: Scalar,
         damping: Scalar,
         jump_impulse: Scalar,
-        max_slope_angle: Scalar,
     ) -> Self {
-        self.movement = MovementBundle::new(acceleration, damping, jump_impulse, max_slope_angle);
+        self.movement = MovementBundle::new(acceleration, damping, jump_impulse);
+        self
+    }
+
+    pub fn with_step_height(mut self, step_height: Scalar) -> Self {
+        self.step_height = StepHeight(step_height);
         self
     }
 }
 
-fn input(
+/// The first and last block the box from `min` to `max` overlaps along each axis, touching
+/// faces don't count.
+fn block_range(min: Vector, max: Vector) -> (IVec3, IVec3) {
+    // Blocks are centered on their positions.
+    let start = (min + 0.5 + EPSILON).floor().as_ivec3();
+    let end = (max + 0.5 - EPSILON).floor().as_ivec3();
+
+    (start, end)
+}
+
+/// Whether any block from `start` to `end` is solid.
+fn any_solid(start: IVec3, end: IVec3, is_solid: &impl Fn(IVec3) -> bool) -> bool {
+    (start.x..=end.x).any(|x| {
+        (start.y..=end.y).any(|y| (start.z..=end.z).any(|z| is_solid(IVec3::new(x, y, z))))
+    })
+}
+
+/// Whether a solid block overlaps the box from `min` to `max`, touching faces don't count.
+pub fn overlaps_solid_block(min: Vector, max: Vector, is_solid: impl Fn(IVec3) -> bool) -> bool {
+    let (start, end) = block_range(min, max);
+
+    any_solid(start, end, &is_solid)
+}
+
+/// How far a box can move along `axis`, up to `distance`, before touching a solid block.
+/// Every layer of blocks along the way is checked, so fast boxes don't tunnel through walls.
+fn sweep_axis(
+    aabb: ColliderAabb,
+    axis: usize,
+    distance: Scalar,
+    is_solid: &impl Fn(IVec3) -> bool,
+) -> Scalar {
+    let (start, end) = block_range(aabb.min, aabb.max);
+    let layer_is_solid = |layer: i32| {
+        let (mut start, mut end) = (start, end);
+
+        start[axis] = layer;
+        end[axis] = layer;
+        any_solid(start, end, is_solid)
+    };
+
+    if distance > 0.0 {
+        let face = aabb.max[axis];
+        let first = (face + 0.5 - EPSILON).floor() as i32 + 1;
+        let last = (face + distance + 0.5 - EPSILON).floor() as i32;
+
+        (first..=last)
+            .find(|&layer| layer_is_solid(layer))
+            .map_or(distance, |layer| {
+                (layer as Scalar - 0.5 - face).clamp(0.0, distance)
+            })
+    } else if distance < 0.0 {
+        let face = aabb.min[axis];
+        let first = (face + 0.5 + EPSILON).floor() as i32 - 1;
+        let last = (face + distance + 0.5 + EPSILON).floor() as i32;
+
+        (last..=first)
+            .rev()
+            .find(|&layer| layer_is_solid(layer))
+            .map_or(distance, |layer| {
+                (layer as Scalar + 0.5 - face).clamp(distance, 0.0)
+            })
+    } else {
+        0.0
+    }
+}
+
+/// Moves a box by `delta` one axis at a time, vertically first, stopping each axis at the
+/// first solid block in the way. Returns how far the box moved.
+fn slide(mut aabb: ColliderAabb, delta: Vector, is_solid: &impl Fn(IVec3) -> bool) -> Vector {
+    let mut moved = Vector::ZERO;
+
+    for axis in [1, 0, 2] {
+        let distance = sweep_axis(aabb, axis, delta[axis], is_solid);
+
+        moved[axis] = distance;
+        aabb.min[axis] += distance;
+        aabb.max[axis] += distance;
+    }
+
+    moved
+}
+
+/// Moves a box by `delta` so that it slides along the blocks it runs into, and returns how
+/// far the box moved. Resolving the axes separately keeps boxes from snagging on the seams
+/// between blocks.
+///
+/// A box on the ground that runs into a ledge up to `step_height` high steps up onto it.
+pub fn collide_and_slide(
+    aabb: ColliderAabb,
+    delta: Vector,
+    step_height: Scalar,
+    is_solid: impl Fn(IVec3) -> bool,
+) -> Vector {
+    let moved = slide(aabb, delta, &is_solid);
+    let on_ground = delta.y <= 0.0 && moved.y != delta.y;
+    let blocked = moved.x != delta.x || moved.z != delta.z;
+
+    if step_height <= 0.0 || !on_ground || !blocked {
+        return moved;
+    }
+
+    // Try again from as high as the step, then back down onto whatever is there.
+    let offset = |aabb: ColliderAabb, offset: Vector| ColliderAabb {
+        min: aabb.min + offset,
+        max: aabb.max + offset,
+    };
+
+    let up = sweep_axis(aabb, 1, step_height, &is_solid);
+    let raised = offset(aabb, Vector::Y * up);
+    let across = slide(raised, Vector::new(delta.x, 0.0, delta.z), &is_solid);
+    let down = sweep_axis(offset(raised, across), 1, -up, &is_solid);
+
+    if across.xz().length_squared() > moved.xz().length_squared() {
+        Vector::new(across.x, up + down, across.z)
+    } else {
+        moved
+    }
+}
+
+/// When forward was last pressed, to start sprinting by double tapping it.
+#[derive(Default)]
+struct ForwardTap {
+    was_forward: bool,
+    last_press: Option<f32>,
+}
+
+/// Looks around every frame, unless the cursor is being used for the inventory screen.
+fn look(
     single: Single<(&ActionState<Action>, &mut WishDir), With<PlayerBody>>,
-    mut movement_event_writer: EventWriter<MovementAction>,
+    inventory_screen: Option<Res<State<InventoryScreen>>>,
 ) {
+    if inventory_screen.is_some_and(|screen| *screen.get() == InventoryScreen::Open) {
+        return;
+    }
+
     let (state, mut wish_dir) = single.into_inner();
-    let wish_dir: &mut Vec2 = &mut wish_dir;
+    let axis = state.axis_pair(&Action::Look).map(f32::to_radians);
+    let (mut yaw, mut pitch) = (**wish_dir).into();
 
-    // look
-    {
-        let axis = state.axis_pair(&Action::Look).map(f32::to_radians);
-        let (mut yaw, mut pitch) = (*wish_dir).into();
+    yaw -= axis.x;
+    pitch -= axis.y;
+    pitch = pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
 
-        yaw -= axis.x;
-        pitch -= axis.y;
-        pitch = pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+    **wish_dir = Vec2::new(yaw, pitch);
+}
 
-        *wish_dir = Vec2::new(yaw, pitch);
-    }
+/// Sends [`MovementAction`] events for the player's input, every tick.
+fn input(
+    time: Res<Time>,
+    mut forward_tap: Local<ForwardTap>,
+    single: Single<(&ActionState<Action>, &WishDir), With<PlayerBody>>,
+    mut movement_event_writer: EventWriter<MovementAction>,
+) {
+    let (state, wish_dir) = *single;
+    let axis = state.axis_pair(&Action::Move);
 
     // move
     {
-        let axis = state.axis_pair(&Action::Move);
         let dir = axis.rotate(Vec2::from_angle(wish_dir.x)) * 2.0;
 
         if dir != Vec2::ZERO {
@@ -181,18 +480,36 @@ fn input(
         }
     }
 
+    // sprint, with the sprint button or by double tapping forward, only while moving forward
+    {
+        let now = time.elapsed_secs();
+        let forward = axis.y > 0.0;
+        let started_forward = forward && !forward_tap.was_forward;
+        let double_tapped = started_forward
+            && forward_tap
+                .last_press
+                .is_some_and(|last_press| now - last_press <= DOUBLE_TAP_TIME);
+
+        if started_forward {
+            forward_tap.last_press = Some(now);
+        }
+
+        forward_tap.was_forward = forward;
+
+        if !forward {
+            movement_event_writer.send(MovementAction::Sprint(false));
+        } else if state.just_pressed(&Action::Sprint)
+            || (started_forward && state.pressed(&Action::Sprint))
+            || double_tapped
+        {
+            movement_event_writer.send(MovementAction::Sprint(true));
+        }
+    }
+
+    movement_event_writer.send(MovementAction::Sneak(state.pressed(&Action::Sneak)));
+
     if state.pressed(&Action::Jump) {
         movement_event_writer.send(MovementAction::Jump);
-    } else if state.just_pressed(&Action::Sneak) {
-        //
-    } else if state.just_pressed(&Action::Sprint) {
-        //
-    } else if state.just_pressed(&Action::Attack) {
-        //
-    } else if state.just_pressed(&Action::Pick) {
-        //
-    } else if state.just_pressed(&Action::Use) {
-        //
     }
 }
 
@@ -202,38 +519,115 @@ fn player_body(single: Single<(&WishDir, &mut Transform), With<PlayerBody>>) {
     transform.rotation = Quat::from_euler(EulerRot::YXZ, wish_dir.x, 0.0, 0.0);
 }
 
+/// Widens the field of view while sprinting.
+fn player_fov(
+    time: Res<Time>,
+    is_sprinting: Single<Has<Sprinting>, With<PlayerBody>>,
+    mut projection: Single<&mut Projection, With<Camera3d>>,
+) {
+    let Projection::Perspective(perspective) = &mut **projection else {
+        return;
+    };
+
+    let fov = if *is_sprinting {
+        FOV * SPRINT_FOV_FACTOR
+    } else {
+        FOV
+    };
+
+    perspective.fov = perspective
+        .fov
+        .lerp(fov, 1.0 - (-10.0 * time.delta_secs()).exp());
+}
+
 fn player_eye(
-    wish_dir: Single<&WishDir, With<PlayerBody>>,
+    time: Res<Time>,
+    player: Single<(&WishDir, &SneakHeight, Has<Sneaking>), With<PlayerBody>>,
     mut transform: Single<&mut Transform, With<PlayerEye>>,
 ) {
+    let (wish_dir, sneak_height, is_sneaking) = *player;
     let (yaw, _pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
 
     transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, wish_dir.y, roll);
+
+    // Ease the eye down while sneaking rather than snapping it.
+    let height = if is_sneaking { -sneak_height.0 } else { 0.0 };
+
+    transform.translation.y = transform
+        .translation
+        .y
+        .lerp(height, 1.0 - (-15.0 * time.delta_secs()).exp());
 }
 
-/// Updates the [`Grounded`] status for character controllers.
-fn update_grounded(
+/// Responds to [`MovementAction::Sneak`] events by shrinking or growing the boxes of
+/// character controllers. Sneaking continues while there's no room to stand up.
+fn sneak(
     mut commands: Commands,
-    mut query: Query<
-        (Entity, &ShapeHits, &Rotation, Option<&MaxSlopeAngle>),
-        With<CharacterController>,
-    >,
+    mut movement_event_reader: EventReader<MovementAction>,
+    chunk_map: Res<ChunkMap>,
+    registry: Res<BlockRegistry>,
+    mut controllers: Query<(
+        Entity,
+        &StandingBox,
+        &SneakHeight,
+        &TickTranslation,
+        &mut CharacterBox,
+        Has<Sneaking>,
+    )>,
 ) {
-    for (entity, hits, rotation, max_slope_angle) in &mut query {
-        // The character is grounded if the shape caster has a hit with a normal
-        // that isn't too steep.
-        let is_grounded = hits.iter().any(|hit| {
-            if let Some(angle) = max_slope_angle {
-                (rotation * -hit.normal2).angle_between(Vector::Y).abs() <= angle.0
+    for event in movement_event_reader.read() {
+        let MovementAction::Sneak(sneak) = *event else {
+            continue;
+        };
+
+        for (entity, standing, height, translation, mut character_box, is_sneaking) in
+            &mut controllers
+        {
+            if sneak == is_sneaking {
+                continue;
+            }
+
+            if sneak {
+                *character_box = standing.0.shrunk(height.0);
+                commands
+                    .entity(entity)
+                    .insert(Sneaking)
+                    .remove::<Sprinting>();
             } else {
-                true
+                let aabb = character_box.aabb(translation.current);
+                let min = Vector::new(aabb.min.x, aabb.max.y, aabb.min.z);
+                let max = aabb.max + Vector::Y * height.0;
+
+                if overlaps_solid_block(min, max, |block| {
+                    registry.is_solid(chunk_map.get_block(block))
+                }) {
+                    continue;
+                }
+
+                *character_box = standing.0;
+                commands.entity(entity).remove::<Sneaking>();
             }
-        });
+        }
+    }
+}
 
-        if is_grounded {
-            commands.entity(entity).insert(Grounded);
-        } else {
-            commands.entity(entity).remove::<Grounded>();
+/// Responds to [`MovementAction::Sprint`] events, sneaking character controllers can't sprint.
+fn sprint(
+    mut commands: Commands,
+    mut movement_event_reader: EventReader<MovementAction>,
+    controllers: Query<(Entity, Has<Sprinting>, Has<Sneaking>), With<CharacterController>>,
+) {
+    for event in movement_event_reader.read() {
+        let MovementAction::Sprint(sprint) = *event else {
+            continue;
+        };
+
+        for (entity, is_sprinting, is_sneaking) in &controllers {
+            if sprint && !is_sprinting && !is_sneaking {
+                commands.entity(entity).insert(Sprinting);
+            } else if !sprint && is_sprinting {
+                commands.entity(entity).remove::<Sprinting>();
+            }
         }
     }
 }
@@ -242,11 +636,15 @@ fn update_grounded(
 fn movement(
     time: Res<Time>,
     mut movement_event_reader: EventReader<MovementAction>,
+    mut jumped: EventWriter<Jumped>,
     mut controllers: Query<(
+        Entity,
         &MovementAcceleration,
         &JumpImpulse,
         &mut LinearVelocity,
         Has<Grounded>,
+        Has<Sneaking>,
+        Has<Sprinting>,
     )>,
 ) {
     // Precision is adjusted so that the example works with
@@ -254,26 +652,43 @@ fn movement(
     let delta_time = time.delta_secs_f64().adjust_precision();
 
     for event in movement_event_reader.read() {
-        for (movement_acceleration, jump_impulse, mut linear_velocity, is_grounded) in
-            &mut controllers
+        for (
+            entity,
+            movement_acceleration,
+            jump_impulse,
+            mut linear_velocity,
+            is_grounded,
+            is_sneaking,
+            is_sprinting,
+        ) in &mut controllers
         {
             match event {
                 MovementAction::Move(direction) => {
-                    linear_velocity.x += direction.x * movement_acceleration.0 * delta_time;
-                    linear_velocity.z -= direction.y * movement_acceleration.0 * delta_time;
+                    let acceleration = if is_sneaking {
+                        movement_acceleration.0 * SNEAK_ACCELERATION_FACTOR
+                    } else if is_sprinting {
+                        movement_acceleration.0 * SPRINT_ACCELERATION_FACTOR
+                    } else {
+                        movement_acceleration.0
+                    };
+
+                    linear_velocity.x += direction.x * acceleration * delta_time;
+                    linear_velocity.z -= direction.y * acceleration * delta_time;
                 }
                 MovementAction::Jump => {
                     if is_grounded {
                         linear_velocity.y = jump_impulse.0;
+                        jumped.send(Jumped { entity });
                     }
                 }
+                MovementAction::Sneak(_) | MovementAction::Sprint(_) => {}
             }
         }
     }
 }
 
 /// Applies [`ControllerGravity`] to character controllers.
-fn apply_gravity(
+pub fn apply_gravity(
     time: Res<Time>,
     mut controllers: Query<(&ControllerGravity, &mut LinearVelocity)>,
 ) {
@@ -295,167 +710,349 @@ fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearV
     }
 }
 
-/// Kinematic bodies do not get pushed by collisions by default,
-/// so it needs to be done manually.
-///
-/// This system handles collision response for kinematic character controllers
-/// by pushing them along their contact normals by the current penetration depth,
-/// and applying velocity corrections in order to snap to slopes, slide along walls,
-/// and predict collisions using speculative contacts.
+/// Keeps sneaking character controllers from walking off the edges of blocks, by cancelling
+/// horizontal velocity along each axis that would leave them without ground underneath.
+fn stop_at_edges(
+    time: Res<Time>,
+    chunk_map: Res<ChunkMap>,
+    registry: Res<BlockRegistry>,
+    mut controllers: Query<
+        (&CharacterBox, &TickTranslation, &mut LinearVelocity),
+        (With<Sneaking>, With<Grounded>),
+    >,
+) {
+    let delta_time = time.delta_secs_f64().adjust_precision();
+
+    for (character_box, translation, mut linear_velocity) in &mut controllers {
+        let aabb = character_box.aabb(translation.current);
+        let supported = |offset: Vector| {
+            let min = aabb.min + offset - Vector::Y * EDGE_SUPPORT_DEPTH;
+            let max = Vector::new(aabb.max.x, aabb.min.y, aabb.max.z) + offset;
+
+            overlaps_solid_block(min, max, |block| {
+                registry.is_solid(chunk_map.get_block(block))
+            })
+        };
+
+        // Standing on something other than blocks, or already over an edge.
+        if !supported(Vector::ZERO) {
+            continue;
+        }
+
+        if !supported(Vector::X * linear_velocity.x * delta_time) {
+            linear_velocity.x = 0.0;
+        }
+
+        if !supported(Vector::Z * linear_velocity.z * delta_time) {
+            linear_velocity.z = 0.0;
+        }
+
+        // Moving diagonally off a corner.
+        let horizontal = Vector::new(linear_velocity.x, 0.0, linear_velocity.z);
+
+        if !supported(horizontal * delta_time) {
+            linear_velocity.x = 0.0;
+            linear_velocity.z = 0.0;
+        }
+    }
+}
+
+/// Moves character controllers by their velocity, sliding along the blocks they run into and
+/// stepping up low ledges. Landing on a block grounds them and sends [`Landed`], and running
+/// into a wall stops sprinting.
 #[allow(clippy::type_complexity)]
-fn kinematic_controller_collisions(
-    collisions: Res<Collisions>,
-    bodies: Query<&RigidBody>,
-    collider_parents: Query<&ColliderParent, Without<Sensor>>,
-    mut character_controllers: Query<
+pub fn move_and_slide(
+    mut commands: Commands,
+    mut stepped: EventWriter<Stepped>,
+    mut landed: EventWriter<Landed>,
+    time: Res<Time>,
+    chunk_map: Res<ChunkMap>,
+    registry: Res<BlockRegistry>,
+    mut controllers: Query<
         (
-            &mut Position,
-            &Rotation,
+            Entity,
+            &CharacterBox,
+            &StepHeight,
+            &mut TickTranslation,
             &mut LinearVelocity,
-            Option<&MaxSlopeAngle>,
+            &ControllerGravity,
+            Has<Grounded>,
+            Has<Sprinting>,
         ),
-        (With<RigidBody>, With<CharacterController>),
+        With<CharacterController>,
     >,
-    time: Res<Time>,
 ) {
-    // Iterate through collisions and move the kinematic body to resolve penetration
-    for contacts in collisions.iter() {
-        // Get the rigid body entities of the colliders (colliders could be children)
-        let Ok([collider_parent1, collider_parent2]) =
-            collider_parents.get_many([contacts.entity1, contacts.entity2])
-        else {
-            continue;
-        };
+    let delta_time = time.delta_secs_f64().adjust_precision();
 
-        // Get the body of the character controller and whether it is the first
-        // or second entity in the collision.
-        let is_first: bool;
-
-        let character_rb: RigidBody;
-        let is_other_dynamic: bool;
-
-        let (mut position, rotation, mut linear_velocity, max_slope_angle) =
-            if let Ok(character) = character_controllers.get_mut(collider_parent1.get()) {
-                is_first = true;
-                character_rb = *bodies.get(collider_parent1.get()).unwrap();
-                is_other_dynamic = bodies
-                    .get(collider_parent2.get())
-                    .is_ok_and(|rb| rb.is_dynamic());
-                character
-            } else if let Ok(character) = character_controllers.get_mut(collider_parent2.get()) {
-                is_first = false;
-                character_rb = *bodies.get(collider_parent2.get()).unwrap();
-                is_other_dynamic = bodies
-                    .get(collider_parent1.get())
-                    .is_ok_and(|rb| rb.is_dynamic());
-                character
-            } else {
-                continue;
-            };
+    for (
+        entity,
+        character_box,
+        step_height,
+        mut translation,
+        mut linear_velocity,
+        gravity,
+        was_grounded,
+        is_sprinting,
+    ) in &mut controllers
+    {
+        let delta = linear_velocity.0 * delta_time;
+        let moved = collide_and_slide(
+            character_box.aabb(translation.current),
+            delta,
+            step_height.0,
+            |block| registry.is_solid(chunk_map.get_block(block)),
+        );
 
-        // This system only handles collision response for kinematic character controllers.
-        if !character_rb.is_kinematic() {
-            continue;
-        }
+        translation.current += moved;
 
-        // Iterate through contact manifolds and their contacts.
-        // Each contact in a single manifold shares the same contact normal.
-        for manifold in contacts.manifolds.iter() {
-            let normal = if is_first {
-                -manifold.global_normal1(rotation)
-            } else {
-                -manifold.global_normal2(rotation)
-            };
+        if moved.y > 0.0 && delta.y <= 0.0 {
+            stepped.send(Stepped {
+                entity,
+                height: moved.y,
+            });
+        }
 
-            let mut deepest_penetration: Scalar = Scalar::MIN;
+        // Stop moving along the axes that ran into a block.
+        let blocked = moved.cmpne(delta);
+        // Gravity was applied for the whole tick, but the block was hit part of the way
+        // through it, so start from the speed at the start of the tick.
+        let start_fall_speed = (gravity.0.y * delta_time - linear_velocity.y).max(0.0);
 
-            // Solve each penetrating contact in the manifold.
-            for contact in manifold.contacts.iter() {
-                if contact.penetration > 0.0 {
-                    position.0 += normal * contact.penetration;
-                }
-                deepest_penetration = deepest_penetration.max(contact.penetration);
+        for axis in 0..3 {
+            if blocked.test(axis) {
+                linear_velocity.0[axis] = 0.0;
             }
+        }
 
-            // For now, this system only handles velocity corrections for collisions against static geometry.
-            if is_other_dynamic {
-                continue;
+        if blocked.y && delta.y < 0.0 {
+            if !was_grounded {
+                // Falling from rest, speed grows with the square root of the distance, and
+                // the distance fallen this tick adds to how far it had fallen before.
+                let gravity_strength = gravity.0.length().max(EPSILON);
+
+                landed.send(Landed {
+                    entity,
+                    fall_height: start_fall_speed * start_fall_speed / (2.0 * gravity_strength)
+                        - moved.y.min(0.0),
+                });
             }
 
-            // Determine if the slope is climbable or if it's too steep to walk on.
-            let slope_angle = normal.angle_between(Vector::Y);
-            let climbable = max_slope_angle.is_some_and(|angle| slope_angle.abs() <= angle.0);
-
-            if deepest_penetration > 0.0 {
-                // If the slope is climbable, snap the velocity so that the character
-                // up and down the surface smoothly.
-                if climbable {
-                    // Points in the normal's direction in the XZ plane.
-                    let normal_direction_xz =
-                        normal.reject_from_normalized(Vector::Y).normalize_or_zero();
-
-                    // The movement speed along the direction above.
-                    let linear_velocity_xz = linear_velocity.dot(normal_direction_xz);
-
-                    // Snap the Y speed based on the speed at which the character is moving
-                    // up or down the slope, and how steep the slope is.
-                    //
-                    // A 2D visualization of the slope, the contact normal, and the velocity components:
-                    //
-                    //             ╱
-                    //     normal ╱
-                    // *         ╱
-                    // │   *    ╱   velocity_x
-                    // │       * - - - - - -
-                    // │           *       | velocity_y
-                    // │               *   |
-                    // *───────────────────*
-
-                    let max_y_speed = -linear_velocity_xz * slope_angle.tan();
-                    linear_velocity.y = linear_velocity.y.max(max_y_speed);
-                } else {
-                    // The character is intersecting an unclimbable object, like a wall.
-                    // We want the character to slide along the surface, similarly to
-                    // a collide-and-slide algorithm.
-
-                    // Don't apply an impulse if the character is moving away from the surface.
-                    if linear_velocity.dot(normal) > 0.0 {
-                        continue;
-                    }
+            commands.entity(entity).insert(Grounded);
+        } else {
+            commands.entity(entity).remove::<Grounded>();
+        }
 
-                    // Slide along the surface, rejecting the velocity along the contact normal.
-                    let impulse = linear_velocity.reject_from_normalized(normal);
-                    linear_velocity.0 = impulse;
-                }
-            } else {
-                // The character is not yet intersecting the other object,
-                // but the narrow phase detected a speculative collision.
-                //
-                // We need to push back the part of the velocity
-                // that would cause penetration within the next frame.
+        if is_sprinting && (blocked.x || blocked.z) {
+            commands.entity(entity).remove::<Sprinting>();
+        }
+    }
+}
 
-                let normal_speed = linear_velocity.dot(normal);
+/// Keeps the [`PlayerEye`] where it was when the player steps up a ledge, so that
+/// [`player_eye`] eases it up rather than it snapping.
+fn smooth_player_eye_steps(
+    mut stepped: EventReader<Stepped>,
+    player: Single<(Entity, &TickTranslation, &Transform), With<PlayerBody>>,
+    mut eye: Single<&mut Transform, (With<PlayerEye>, Without<PlayerBody>)>,
+) {
+    let (entity, translation, transform) = *player;
 
-                // Don't apply an impulse if the character is moving away from the surface.
-                if normal_speed > 0.0 {
-                    continue;
-                }
+    for event in stepped.read() {
+        if event.entity != entity {
+            continue;
+        }
 
-                // Compute the impulse to apply.
-                let impulse_magnitude =
-                    normal_speed - (deepest_penetration / time.delta_secs_f64().adjust_precision());
-                let mut impulse = impulse_magnitude * normal;
-
-                // Apply the impulse differently depending on the slope angle.
-                if climbable {
-                    // Avoid sliding down slopes.
-                    linear_velocity.y -= impulse.y.min(0.0);
-                } else {
-                    // Avoid climbing up walls.
-                    impulse.y = impulse.y.max(0.0);
-                    linear_velocity.0 -= impulse;
-                }
+        // The body is only part of the way up until interpolation catches up with the tick
+        // that stepped, so lowering the eye by the whole step would dip it below where it was.
+        let not_interpolated =
+            (translation.current.y - transform.translation.y).clamp(0.0, event.height);
+
+        eye.translation.y -= event.height - not_interpolated;
+    }
+}
+
+/// Remembers where character controllers were before this tick, to interpolate from.
+fn store_previous_translation(mut controllers: Query<&mut TickTranslation>) {
+    for mut translation in &mut controllers {
+        translation.previous = translation.current;
+    }
+}
+
+/// Moves character controllers between where they were on the last two ticks, by how far
+/// along the next tick is.
+fn interpolate_translation(
+    time: Res<Time<Fixed>>,
+    mut controllers: Query<(&TickTranslation, &mut Transform)>,
+) {
+    let fraction = time.overstep_fraction();
+
+    for (translation, mut transform) in &mut controllers {
+        transform.translation = translation.previous.lerp(translation.current, fraction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use crate::world::BlockId;
+    use crate::PLAYER_SIZE;
+    use bevy::state::app::StatesPlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    /// The top of the floor the player walks on.
+    const FLOOR: Scalar = -0.5;
+
+    /// Walks the player forward along a floor for a second at `fps` frames per second,
+    /// returning where they are shown at the end.
+    fn walk_for_a_second(fps: u32) -> Vec3 {
+        let mut app = App::new();
+        let mut chunk_map = ChunkMap::default();
+
+        for x in -16..16 {
+            for z in -16..16 {
+                chunk_map.set_block(IVec3::new(x, -1, z), BlockId(1));
             }
         }
+
+        app.add_plugins((MinimalPlugins, StatesPlugin, CharacterControllerPlugin))
+            .insert_state(GameState::InGame)
+            .insert_resource(chunk_map)
+            .insert_resource(registry(&["stone"]))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+
+        let mut action_state = ActionState::<Action>::default();
+
+        action_state.set_axis_pair(&Action::Move, Vec2::Y);
+
+        let player = app
+            .world_mut()
+            .spawn((
+                PlayerBody,
+                WishDir(Vec2::ZERO),
+                action_state,
+                CharacterControllerBundle::new(PLAYER_SIZE, Vector::NEG_Y * 9.81 * 2.0),
+                Grounded,
+                Transform::from_xyz(0.0, FLOOR + PLAYER_SIZE.y / 2.0, 0.0),
+            ))
+            .id();
+
+        // The first update only starts the clock.
+        app.update();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(
+            Duration::from_secs(1) / fps,
+        ));
+
+        for _ in 0..fps {
+            app.update();
+        }
+
+        app.world().get::<Transform>(player).unwrap().translation
+    }
+
+    #[test]
+    fn distance_does_not_depend_on_frame_rate() {
+        let slow = walk_for_a_second(30);
+        let fast = walk_for_a_second(144);
+
+        assert!(slow.xz().length() > 1.0, "didn't walk: {slow}");
+        assert!(slow.distance(fast) < 1e-3, "{slow} != {fast}");
+
+        // Both stayed on the floor rather than sinking into it or bouncing off it.
+        let standing = FLOOR + PLAYER_SIZE.y / 2.0;
+
+        assert!((slow.y - standing).abs() < 1e-4, "{slow}");
+        assert!((fast.y - standing).abs() < 1e-4, "{fast}");
+    }
+
+    /// A box the size of the player, centered on the origin horizontally.
+    fn player_box(bottom: Scalar) -> ColliderAabb {
+        ColliderAabb {
+            min: Vector::new(-0.3, bottom, -0.3),
+            max: Vector::new(0.3, bottom + 1.8, 0.3),
+        }
+    }
+
+    /// A floor with its top at 0.5, and a ledge from x = 0.5 onwards with its top at `top` + 0.5.
+    fn ledge(top: i32) -> impl Fn(IVec3) -> bool {
+        move |block| block.y <= 0 || (block.x >= 1 && block.y <= top)
+    }
+
+    fn assert_moved(moved: Vector, expected: Vector) {
+        assert!(moved.abs_diff_eq(expected, 1e-5), "{moved} != {expected}");
+    }
+
+    #[test]
+    fn steps_up_a_block() {
+        let moved = collide_and_slide(player_box(0.5), Vector::new(0.5, -0.1, 0.0), 1.0, ledge(1));
+
+        assert_moved(moved, Vector::new(0.5, 1.0, 0.0));
+    }
+
+    #[test]
+    fn steps_up_a_slab() {
+        // Blocks are whole, so drop the box half a block onto the floor to leave the ledge
+        // half a block above where it started.
+        let moved = collide_and_slide(player_box(1.0), Vector::new(0.5, -1.0, 0.0), 1.0, ledge(1));
+
+        assert_moved(moved, Vector::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn refuses_ledges_above_the_step_height() {
+        // As with the slab, the ledge ends up one and a half blocks above the box.
+        let moved = collide_and_slide(player_box(1.0), Vector::new(0.5, -1.0, 0.0), 1.0, ledge(2));
+
+        assert_moved(moved, Vector::new(0.2, -0.5, 0.0));
+    }
+
+    #[test]
+    fn does_not_step_in_mid_air() {
+        let wall = |block: IVec3| block.x >= 1 && block.y <= 1;
+        let moved = collide_and_slide(player_box(0.5), Vector::new(0.5, -0.1, 0.0), 1.0, wall);
+
+        assert_moved(moved, Vector::new(0.2, -0.1, 0.0));
+    }
+
+    #[test]
+    fn slides_along_seams_in_the_floor() {
+        let floor = |block: IVec3| block.y <= 0;
+        let moved = collide_and_slide(player_box(0.5), Vector::new(20.0, -0.1, 7.5), 0.0, floor);
+
+        assert_moved(moved, Vector::new(20.0, 0.0, 7.5));
+    }
+
+    #[test]
+    fn fast_boxes_stop_at_thin_walls() {
+        let wall = |block: IVec3| block.x == 3;
+        let moved = collide_and_slide(player_box(0.5), Vector::new(10.0, 0.0, 0.0), 0.0, wall);
+
+        assert_moved(moved, Vector::new(2.2, 0.0, 0.0));
+
+        let moved = collide_and_slide(
+            player_box(0.5),
+            Vector::new(-10.0, 0.0, 0.0),
+            0.0,
+            |block| block.x == -3,
+        );
+
+        assert_moved(moved, Vector::new(-2.2, 0.0, 0.0));
+    }
+
+    #[test]
+    fn touching_faces_do_not_collide() {
+        // The box stands on the floor, with its side against a wall.
+        let solid = |block: IVec3| block.y <= 0 || block.x >= 1;
+        let aabb = ColliderAabb {
+            min: Vector::new(-0.1, 0.5, -0.3),
+            max: Vector::new(0.5, 2.3, 0.3),
+        };
+
+        assert!(!overlaps_solid_block(aabb.min, aabb.max, solid));
+        assert_moved(
+            collide_and_slide(aabb, Vector::new(0.0, 0.0, 3.0), 0.0, solid),
+            Vector::new(0.0, 0.0, 3.0),
+        );
     }
 }
@@ -0,0 +1,205 @@
+use crate::world::ChunkMap;
+use crate::{GameState, PlayerEye};
+use bevy::prelude::*;
+
+/// Finds the block the player is looking at every frame.
+pub struct RaycastPlugin;
+
+impl Plugin for RaycastPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Reach>()
+            .init_resource::<TargetBlock>()
+            .add_systems(
+                Update,
+                update_target_block.run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// How far away the player can target blocks, in blocks.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct Reach(pub f32);
+
+impl Default for Reach {
+    fn default() -> Self {
+        Self(5.0)
+    }
+}
+
+/// The block the player is looking at, if it's within reach.
+#[derive(Clone, Copy, Debug, Default, Deref, Resource)]
+pub struct TargetBlock(pub Option<RayHit>);
+
+/// A block hit by a [`raycast`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub block: IVec3,
+    /// The normal of the face the ray entered the block through.
+    pub normal: IVec3,
+    /// How far along the ray the face is.
+    pub distance: f32,
+}
+
+/// Walks the grid of blocks along a ray, returning the first block `is_target` accepts.
+///
+/// The block the ray starts in is skipped, so that a ray from inside a block can still
+/// target the blocks around it.
+pub fn raycast(
+    ray: Ray3d,
+    max_distance: f32,
+    mut is_target: impl FnMut(IVec3) -> bool,
+) -> Option<RayHit> {
+    // Blocks are centered on their position, so offset the ray to put block edges on integers.
+    let start = ray.origin + 0.5;
+    let direction = *ray.direction;
+
+    let mut block = start.floor().as_ivec3();
+    let mut step = IVec3::ZERO;
+    // The distance along the ray to the next block edge on each axis.
+    let mut next = Vec3::INFINITY;
+    // The distance along the ray between block edges on each axis.
+    let mut delta = Vec3::INFINITY;
+
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            next[axis] = (block[axis] as f32 + 1.0 - start[axis]) / direction[axis];
+            delta[axis] = 1.0 / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            next[axis] = (block[axis] as f32 - start[axis]) / direction[axis];
+            delta[axis] = -1.0 / direction[axis];
+        }
+    }
+
+    loop {
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+
+        let distance = next[axis];
+
+        if distance > max_distance {
+            return None;
+        }
+
+        block[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        if is_target(block) {
+            let mut normal = IVec3::ZERO;
+
+            normal[axis] = -step[axis];
+
+            return Some(RayHit {
+                block,
+                normal,
+                distance,
+            });
+        }
+    }
+}
+
+/// Casts a ray from the player's eye along the view direction to find the [`TargetBlock`].
+pub fn update_target_block(
+    mut target: ResMut<TargetBlock>,
+    chunk_map: Res<ChunkMap>,
+    reach: Res<Reach>,
+    eye: Single<&GlobalTransform, With<PlayerEye>>,
+) {
+    let ray = Ray3d::new(eye.translation(), eye.forward());
+    let hit = raycast(ray, reach.0, |block| !chunk_map.get_block(block).is_air());
+
+    if target.0 != hit {
+        target.0 = hit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn cast(origin: Vec3, direction: Vec3, max_distance: f32, solid: &[IVec3]) -> Option<RayHit> {
+        let solid: HashSet<_> = solid.iter().copied().collect();
+        let ray = Ray3d::new(origin, Dir3::new(direction).unwrap());
+
+        raycast(ray, max_distance, |block| solid.contains(&block))
+    }
+
+    #[test]
+    fn hits_along_an_axis() {
+        let hit = cast(Vec3::ZERO, Vec3::X, 5.0, &[IVec3::new(3, 0, 0)]).unwrap();
+
+        assert_eq!(hit.block, IVec3::new(3, 0, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hits_diagonally() {
+        // Passes just over the blocks below the target.
+        let hit = cast(
+            Vec3::ZERO,
+            Vec3::new(1.0, 0.5, 0.0),
+            5.0,
+            &[
+                IVec3::new(4, 1, 0),
+                IVec3::new(4, 2, 0),
+                IVec3::new(3, 0, 0),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(hit.block, IVec3::new(4, 2, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 3.5 * 1.25f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn normal_faces_the_ray() {
+        for direction in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            // Slightly off center, so the ray isn't along a block edge.
+            let origin = (-direction * 3).as_vec3() + 0.1;
+            let hit = cast(origin, direction.as_vec3(), 5.0, &[IVec3::ZERO]).unwrap();
+
+            assert_eq!(hit.block, IVec3::ZERO, "{direction}");
+            assert_eq!(hit.normal, -direction, "{direction}");
+        }
+    }
+
+    #[test]
+    fn misses_beyond_reach() {
+        let solid = [IVec3::new(5, 0, 0)];
+
+        assert_eq!(cast(Vec3::ZERO, Vec3::X, 4.4, &solid), None);
+        assert!(cast(Vec3::ZERO, Vec3::X, 4.6, &solid).is_some());
+        assert_eq!(cast(Vec3::ZERO, Vec3::NEG_X, 100.0, &solid), None);
+    }
+
+    #[test]
+    fn skips_the_starting_block() {
+        let hit = cast(
+            Vec3::ZERO,
+            Vec3::X,
+            5.0,
+            &[IVec3::ZERO, IVec3::new(1, 0, 0)],
+        )
+        .unwrap();
+
+        assert_eq!(hit.block, IVec3::new(1, 0, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 0.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,519 @@
+//! Worlds are saved to a directory holding `level.ron` and a `region` directory.
+//!
+//! Each region file holds a cube of `REGION_SIZE`³ chunks, and starts with a header
+//! followed by the zlib compressed chunks:
+//!
+//! ```text
+//! magic:   b"MCRG"
+//! version: u32
+//! table:   [(offset: u32, length: u32); REGION_VOLUME], an offset of 0 means the chunk is absent
+//! ```
+//!
+//! Chunks decompress to their blocks as little endian `u16`s, in the order of [`Chunk::blocks`].
+//! All integers are little endian.
+
+use crate::world::{BlockId, Chunk, ChunkMap, WorldTime, CHUNK_VOLUME};
+use crate::worldgen::GeneratorKind;
+use crate::{PlayerBody, WishDir};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The length of a region along each axis, in chunks.
+pub const REGION_SIZE: i32 = 8;
+
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"MCRG";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + REGION_VOLUME * 8;
+
+/// How often the world is saved while playing.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AutosaveTimer(Timer::new(
+            AUTOSAVE_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Update, autosave.run_if(resource_exists::<WorldSave>))
+        .add_systems(Last, save_on_exit.run_if(resource_exists::<WorldSave>));
+    }
+}
+
+/// Everything about a world that isn't a chunk.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LevelData {
+    pub seed: u64,
+    pub generator: GeneratorKind,
+    pub spawn: [i32; 3],
+    pub time: f64,
+    pub player: PlayerData,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlayerData {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// The parts of [`LevelData`] that don't change while playing.
+#[derive(Clone, Debug, Resource)]
+pub struct LevelInfo {
+    pub seed: u64,
+    pub generator: GeneratorKind,
+    pub spawn: IVec3,
+}
+
+/// The directory the current world is saved to.
+#[derive(Clone, Debug, Resource)]
+pub struct WorldSave {
+    dir: PathBuf,
+}
+
+impl WorldSave {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn level_path(&self) -> PathBuf {
+        self.dir.join("level.ron")
+    }
+
+    fn region_path(&self, region_position: IVec3) -> PathBuf {
+        let IVec3 { x, y, z } = region_position;
+
+        self.dir
+            .join("region")
+            .join(format!("r.{x}.{y}.{z}.region"))
+    }
+
+    /// Loads the level data, or `None` if the world hasn't been saved yet.
+    pub fn load_level(&self) -> io::Result<Option<LevelData>> {
+        let text = match fs::read_to_string(self.level_path()) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        ron::from_str(&text)
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn save_level(&self, level: &LevelData) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        write_atomic(&self.level_path(), text.as_bytes())
+    }
+
+    /// Loads a single chunk, or `None` if it hasn't been saved.
+    pub fn load_chunk(&self, chunk_position: IVec3) -> io::Result<Option<Chunk>> {
+        let (region_position, index) = split_chunk_position(chunk_position);
+
+        let mut file = match File::open(self.region_path(region_position)) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let mut header = vec![0; HEADER_SIZE];
+
+        file.read_exact(&mut header)?;
+
+        let Some((offset, length)) = parse_header(&header)?[index] else {
+            return Ok(None);
+        };
+
+        let mut data = vec![0; length as usize];
+
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut data)?;
+
+        decode_chunk(&data).map(Some)
+    }
+
+    /// Saves chunks, leaving the other chunks in their regions untouched.
+    pub fn save_chunks<'a>(
+        &self,
+        chunks: impl IntoIterator<Item = (IVec3, &'a Chunk)>,
+    ) -> io::Result<()> {
+        let mut regions: Vec<(IVec3, Vec<(usize, &Chunk)>)> = Vec::new();
+
+        for (chunk_position, chunk) in chunks {
+            let (region_position, index) = split_chunk_position(chunk_position);
+
+            match regions
+                .iter_mut()
+                .find(|(position, _)| *position == region_position)
+            {
+                Some((_, chunks)) => chunks.push((index, chunk)),
+                None => regions.push((region_position, vec![(index, chunk)])),
+            }
+        }
+
+        for (region_position, chunks) in regions {
+            let path = self.region_path(region_position);
+            let mut entries = match fs::read(&path) {
+                Ok(bytes) => read_region(&bytes)?,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    vec![None; REGION_VOLUME]
+                }
+                Err(error) => return Err(error),
+            };
+
+            for (index, chunk) in chunks {
+                entries[index] = Some(encode_chunk(chunk)?);
+            }
+
+            write_atomic(&path, &write_region(&entries))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a chunk position into the position of its region, and its index within that region.
+fn split_chunk_position(chunk_position: IVec3) -> (IVec3, usize) {
+    let size = IVec3::splat(REGION_SIZE);
+    let local = chunk_position.rem_euclid(size);
+    let index = local.x + local.z * REGION_SIZE + local.y * REGION_SIZE * REGION_SIZE;
+
+    (chunk_position.div_euclid(size), index as usize)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Parses the offset table of a region file.
+fn parse_header(header: &[u8]) -> io::Result<Vec<Option<(u32, u32)>>> {
+    if header.len() < HEADER_SIZE || &header[0..4] != MAGIC {
+        return Err(invalid_data("not a region file"));
+    }
+
+    if read_u32(header, 4) != VERSION {
+        return Err(invalid_data("unsupported region version"));
+    }
+
+    let entries = (0..REGION_VOLUME)
+        .map(|index| {
+            let offset = read_u32(header, 8 + index * 8);
+            let length = read_u32(header, 12 + index * 8);
+
+            (offset != 0).then_some((offset, length))
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Reads the compressed chunks of a region file.
+fn read_region(bytes: &[u8]) -> io::Result<Vec<Option<Vec<u8>>>> {
+    parse_header(bytes)?
+        .into_iter()
+        .map(|entry| {
+            let Some((offset, length)) = entry else {
+                return Ok(None);
+            };
+
+            let range = offset as usize..offset as usize + length as usize;
+
+            bytes
+                .get(range)
+                .map(|data| Some(data.to_vec()))
+                .ok_or_else(|| invalid_data("chunk extends past the end of the region"))
+        })
+        .collect()
+}
+
+/// Writes compressed chunks into a region file.
+fn write_region(entries: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let mut region = Vec::with_capacity(HEADER_SIZE);
+    let mut data = Vec::new();
+
+    region.extend_from_slice(MAGIC);
+    region.extend_from_slice(&VERSION.to_le_bytes());
+
+    for entry in entries {
+        let (offset, length) = match entry {
+            Some(chunk) => {
+                let offset = HEADER_SIZE + data.len();
+
+                data.extend_from_slice(chunk);
+
+                (offset as u32, chunk.len() as u32)
+            }
+            None => (0, 0),
+        };
+
+        region.extend_from_slice(&offset.to_le_bytes());
+        region.extend_from_slice(&length.to_le_bytes());
+    }
+
+    region.extend_from_slice(&data);
+    region
+}
+
+fn encode_chunk(chunk: &Chunk) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+    for block in chunk.blocks() {
+        encoder.write_all(&block.0.to_le_bytes())?;
+    }
+
+    encoder.finish()
+}
+
+fn decode_chunk(data: &[u8]) -> io::Result<Chunk> {
+    let mut bytes = Vec::with_capacity(CHUNK_VOLUME * 2);
+
+    ZlibDecoder::new(data).read_to_end(&mut bytes)?;
+
+    let blocks = bytes
+        .chunks_exact(2)
+        .map(|block| BlockId(u16::from_le_bytes([block[0], block[1]])))
+        .collect();
+
+    Chunk::from_blocks(blocks).ok_or_else(|| invalid_data("chunk has the wrong number of blocks"))
+}
+
+/// Writes to a temporary file first, so that a crash never leaves a half written file behind,
+/// and readers never see one.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temporary = path.with_extension("tmp");
+
+    fs::write(&temporary, bytes)?;
+    fs::rename(temporary, path)
+}
+
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
+/// Saves every modified chunk, including unloaded ones, along with the level data. Chunks that
+/// failed to save stay marked as modified, to be saved again next time.
+pub fn save_world(save: &WorldSave, chunk_map: &mut ChunkMap, level: &LevelData) -> io::Result<()> {
+    let unsaved = chunk_map.take_unsaved();
+    let chunks = unsaved
+        .iter()
+        .filter_map(|&position| Some((position, chunk_map.unsaved_chunk(position)?)));
+
+    if let Err(error) = save.save_chunks(chunks) {
+        chunk_map.restore_unsaved(unsaved);
+        return Err(error);
+    }
+
+    chunk_map.clear_unloaded();
+
+    save.save_level(level)?;
+
+    info!("saved {} chunks", unsaved.len());
+
+    Ok(())
+}
+
+fn level_data(
+    info: &LevelInfo,
+    time: &WorldTime,
+    transform: &Transform,
+    wish_dir: &WishDir,
+) -> LevelData {
+    LevelData {
+        seed: info.seed,
+        generator: info.generator,
+        spawn: info.spawn.to_array(),
+        time: time.0,
+        player: PlayerData {
+            position: transform.translation.to_array(),
+            yaw: wish_dir.x,
+            pitch: wish_dir.y,
+        },
+    }
+}
+
+fn autosave(
+    time: Res<Time>,
+    mut timer: ResMut<AutosaveTimer>,
+    mut chunk_map: ResMut<ChunkMap>,
+    save: Res<WorldSave>,
+    info: Res<LevelInfo>,
+    world_time: Res<WorldTime>,
+    player: Single<(&Transform, &WishDir), With<PlayerBody>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let (transform, wish_dir) = *player;
+    let level = level_data(&info, &world_time, transform, wish_dir);
+
+    if let Err(error) = save_world(&save, &mut chunk_map, &level) {
+        error!("failed to save world: {error}");
+    }
+}
+
+fn save_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    mut chunk_map: ResMut<ChunkMap>,
+    save: Res<WorldSave>,
+    info: Res<LevelInfo>,
+    world_time: Res<WorldTime>,
+    player: Single<(&Transform, &WishDir), With<PlayerBody>>,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+
+    let (transform, wish_dir) = *player;
+    let level = level_data(&info, &world_time, transform, wish_dir);
+
+    if let Err(error) = save_world(&save, &mut chunk_map, &level) {
+        error!("failed to save world: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A save in a fresh temporary directory.
+    fn temporary_save(name: &str) -> WorldSave {
+        let dir = std::env::temp_dir().join(format!("maricraft-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        WorldSave::new(dir)
+    }
+
+    fn chunk_with(blocks: &[(IVec3, u16)]) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for &(local, block) in blocks {
+            chunk.set(local, BlockId(block));
+        }
+
+        chunk
+    }
+
+    fn level() -> LevelData {
+        LevelData {
+            seed: 1234,
+            generator: GeneratorKind::Superflat,
+            spawn: [0, 12, -3],
+            time: 456.5,
+            player: PlayerData {
+                position: [1.5, 12.0, -2.5],
+                yaw: 0.25,
+                pitch: -0.5,
+            },
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let save = temporary_save("chunks");
+        let a = chunk_with(&[(IVec3::ZERO, 1), (IVec3::new(15, 15, 15), 2)]);
+        let b = chunk_with(&[(IVec3::new(3, 4, 5), 3)]);
+
+        // The second chunk shares the region, and saving it mustn't drop the first.
+        save.save_chunks([(IVec3::new(-1, 2, -3), &a)]).unwrap();
+        save.save_chunks([(IVec3::new(-2, 2, -3), &b)]).unwrap();
+
+        let loaded_a = save.load_chunk(IVec3::new(-1, 2, -3)).unwrap().unwrap();
+        let loaded_b = save.load_chunk(IVec3::new(-2, 2, -3)).unwrap().unwrap();
+
+        assert_eq!(loaded_a.blocks(), a.blocks());
+        assert_eq!(loaded_b.blocks(), b.blocks());
+
+        fs::remove_dir_all(&save.dir).unwrap();
+    }
+
+    #[test]
+    fn absent_chunks_are_none() {
+        let save = temporary_save("absent");
+
+        // Without a region file, then with one that doesn't hold the chunk.
+        assert!(save.load_chunk(IVec3::ZERO).unwrap().is_none());
+
+        save.save_chunks([(IVec3::ZERO, &chunk_with(&[(IVec3::ZERO, 1)]))])
+            .unwrap();
+
+        assert!(save.load_chunk(IVec3::X).unwrap().is_none());
+
+        fs::remove_dir_all(&save.dir).unwrap();
+    }
+
+    #[test]
+    fn level_round_trips() {
+        let save = temporary_save("level");
+
+        assert_eq!(save.load_level().unwrap(), None);
+
+        save.save_level(&level()).unwrap();
+
+        assert_eq!(save.load_level().unwrap(), Some(level()));
+
+        fs::remove_dir_all(&save.dir).unwrap();
+    }
+
+    #[test]
+    fn failed_saves_keep_chunks_unsaved() {
+        let save = temporary_save("failed");
+        let mut chunk_map = ChunkMap::default();
+
+        chunk_map.set_block(IVec3::ZERO, BlockId(1));
+
+        // A directory in place of the region file can't be read.
+        fs::create_dir_all(save.region_path(IVec3::ZERO)).unwrap();
+
+        assert!(save_world(&save, &mut chunk_map, &level()).is_err());
+        assert!(chunk_map.is_unsaved(IVec3::ZERO));
+
+        fs::remove_dir_all(&save.dir).unwrap();
+    }
+
+    #[test]
+    fn unloaded_chunks_are_saved() {
+        let save = temporary_save("unloaded");
+        let mut chunk_map = ChunkMap::default();
+
+        chunk_map.set_block(IVec3::ZERO, BlockId(1));
+        chunk_map.set_block(IVec3::splat(16), BlockId(2));
+        chunk_map.unload_chunk(IVec3::ZERO);
+        chunk_map.unload_chunk(IVec3::ONE);
+
+        // Coming back before the save loads the modified chunk rather than the saved one.
+        assert!(chunk_map.reload_chunk(IVec3::ONE));
+        assert!(chunk_map.is_unsaved(IVec3::ONE));
+
+        save_world(&save, &mut chunk_map, &level()).unwrap();
+
+        let loaded = save.load_chunk(IVec3::ZERO).unwrap().unwrap();
+
+        assert_eq!(loaded.get(IVec3::ZERO), BlockId(1));
+        assert!(!chunk_map.contains_chunk(IVec3::ZERO));
+        assert!(!chunk_map.reload_chunk(IVec3::ZERO));
+        assert!(save.load_chunk(IVec3::ONE).unwrap().is_some());
+
+        fs::remove_dir_all(&save.dir).unwrap();
+    }
+}
@@ -0,0 +1,663 @@
+//! Sponge schematics, gzipped NBT files holding a box of blocks, for sharing structures
+//! between worlds and with other tools.
+//!
+//! Versions 1 to 3 can be read, and versions 2 and 3 written. Blocks are stored as a palette
+//! of block states along with varint palette indices, ordered by Y, then Z, then X.
+
+use crate::block::BlockRegistry;
+use crate::nbt::{self, Compound, Tag};
+use crate::raycast::TargetBlock;
+use crate::world::{split_position, ChunkMap};
+use crate::{Action, GameState, PlayerBody};
+use bevy::prelude::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use leafwing_input_manager::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The Minecraft Java Edition data version written to schematics, 1.20.1.
+const DATA_VERSION: i32 = 3465;
+
+/// Selects a region with `Action::SelectFrom` and `Action::SelectTo`, exports it with
+/// `Action::Export` and pastes the exported schematic with `Action::Paste`.
+pub struct SchematicPlugin;
+
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SchematicSettings>()
+            .init_resource::<SchematicSelection>()
+            .add_systems(
+                Update,
+                (select_region, export_selection, paste_schematic)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Which version of the format schematics are written in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SchematicVersion {
+    V2,
+    #[default]
+    V3,
+}
+
+/// Where schematics are exported to and pasted from.
+#[derive(Clone, Debug, Resource)]
+pub struct SchematicSettings {
+    pub path: PathBuf,
+    pub version: SchematicVersion,
+}
+
+impl Default for SchematicSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("schematics/clipboard.schem"),
+            version: SchematicVersion::default(),
+        }
+    }
+}
+
+/// The corners of the region to export, and the rotation pastes use.
+#[derive(Debug, Default, Resource)]
+struct SchematicSelection {
+    from: Option<IVec3>,
+    to: Option<IVec3>,
+    /// Quarter turns clockwise, seen from above.
+    quarter_turns: u8,
+}
+
+/// A box of blocks, named by their vanilla block states.
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    pub size: IVec3,
+    /// Where the box is relative to the position it's pasted at.
+    pub offset: IVec3,
+    pub palette: Vec<String>,
+    /// Indices into `palette`, ordered by Y, then Z, then X.
+    pub blocks: Vec<u32>,
+}
+
+/// What a paste did, including which blocks had to be replaced by the placeholder.
+#[derive(Debug, Default)]
+pub struct PasteSummary {
+    pub placed: usize,
+    /// Blocks that fell in chunks that aren't loaded.
+    pub skipped: usize,
+    pub unknown: HashMap<String, usize>,
+}
+
+impl fmt::Display for PasteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pasted {} blocks", self.placed)?;
+
+        if self.skipped > 0 {
+            write!(f, ", skipped {} in unloaded chunks", self.skipped)?;
+        }
+
+        for (name, count) in &self.unknown {
+            write!(f, "\n  unknown {name}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn get_int(compound: &Compound, name: &str) -> Option<i64> {
+    compound.get(name).and_then(Tag::as_i64)
+}
+
+/// Rotates a position around the Y axis by quarter turns, clockwise seen from above.
+fn rotate(position: IVec3, quarter_turns: u8) -> IVec3 {
+    let IVec3 { x, y, z } = position;
+
+    match quarter_turns % 4 {
+        0 => position,
+        1 => IVec3::new(-z, y, x),
+        2 => IVec3::new(-x, y, -z),
+        _ => IVec3::new(z, y, -x),
+    }
+}
+
+/// The name of a block state without its properties, `minecraft:oak_log[axis=y]` becomes
+/// `minecraft:oak_log`.
+fn block_name(state: &str) -> &str {
+    state.split_once('[').map_or(state, |(name, _)| name)
+}
+
+fn read_varints(bytes: &[i8], count: usize) -> io::Result<Vec<u32>> {
+    // Every varint takes at least a byte, so a larger count can't match, and checking first
+    // keeps a corrupt size from allocating far too much.
+    if count > bytes.len() {
+        return Err(invalid_data("block data doesn't match the schematic size"));
+    }
+
+    let mut values = Vec::with_capacity(count);
+    let mut value = 0;
+    let mut shift = 0;
+
+    for &byte in bytes {
+        let byte = byte as u8;
+
+        value |= u32::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+
+            if shift > 28 {
+                return Err(invalid_data("varint is too long"));
+            }
+        }
+    }
+
+    if values.len() != count {
+        return Err(invalid_data("block data doesn't match the schematic size"));
+    }
+
+    Ok(values)
+}
+
+fn write_varints(values: &[u32]) -> Vec<i8> {
+    let mut bytes = Vec::with_capacity(values.len());
+
+    for &value in values {
+        let mut value = value;
+
+        while value >= 0x80 {
+            bytes.push((value as u8 | 0x80) as i8);
+            value >>= 7;
+        }
+
+        bytes.push(value as i8);
+    }
+
+    bytes
+}
+
+impl Schematic {
+    fn volume(&self) -> usize {
+        self.size.x as usize * self.size.y as usize * self.size.z as usize
+    }
+
+    /// Copies the box between two corners, offset so that pasting at `from` puts it back.
+    pub fn copy(chunk_map: &ChunkMap, registry: &BlockRegistry, from: IVec3, to: IVec3) -> Self {
+        let min = from.min(to);
+        let size = (from - to).abs() + IVec3::ONE;
+
+        let mut palette = Vec::new();
+        // Different blocks may be exported as the same vanilla block, so the palette is keyed
+        // by name and blocks only cache their index.
+        let mut names = HashMap::new();
+        let mut indices = HashMap::new();
+        let mut blocks = Vec::with_capacity(size.x as usize * size.y as usize * size.z as usize);
+
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    let block = chunk_map.get_block(min + IVec3::new(x, y, z));
+                    let index = *indices.entry(block).or_insert_with(|| {
+                        let name = registry
+                            .get(block)
+                            .map_or("minecraft:air", |block| &block.vanilla);
+
+                        *names.entry(name.to_string()).or_insert_with(|| {
+                            palette.push(name.to_string());
+                            palette.len() as u32 - 1
+                        })
+                    });
+
+                    blocks.push(index);
+                }
+            }
+        }
+
+        Self {
+            size,
+            offset: min - from,
+            palette,
+            blocks,
+        }
+    }
+
+    /// Pastes the schematic at `position`, rotated around it by quarter turns.
+    ///
+    /// Blocks that would land in unloaded chunks are skipped, since those chunks would
+    /// replace them once they load.
+    pub fn paste(
+        &self,
+        chunk_map: &mut ChunkMap,
+        registry: &BlockRegistry,
+        position: IVec3,
+        quarter_turns: u8,
+    ) -> PasteSummary {
+        let mapped: Vec<_> = self
+            .palette
+            .iter()
+            .map(|state| registry.by_vanilla_name(block_name(state)))
+            .collect();
+
+        let placeholder = registry.placeholder();
+        let mut summary = PasteSummary::default();
+
+        let (width, length) = (self.size.x as usize, self.size.z as usize);
+
+        for (index, &palette_index) in self.blocks.iter().enumerate() {
+            let local = IVec3::new(
+                (index % width) as i32,
+                (index / (width * length)) as i32,
+                (index / width % length) as i32,
+            );
+
+            let target = position + rotate(local + self.offset, quarter_turns);
+
+            if !chunk_map.contains_chunk(split_position(target).0) {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let block = match mapped.get(palette_index as usize) {
+                Some(Some(block)) => *block,
+                _ => {
+                    let name = self
+                        .palette
+                        .get(palette_index as usize)
+                        .map_or("", |state| block_name(state));
+
+                    *summary.unknown.entry(name.to_string()).or_default() += 1;
+                    placeholder
+                }
+            };
+
+            chunk_map.set_block(target, block);
+            summary.placed += 1;
+        }
+
+        summary
+    }
+
+    /// Reads a gzipped schematic of any version.
+    pub fn read(bytes: &[u8]) -> io::Result<Self> {
+        let mut data = Vec::new();
+
+        GzDecoder::new(bytes).read_to_end(&mut data)?;
+
+        let (_, root) = nbt::read(&data)?;
+
+        // Version 3 nests everything in a `Schematic` compound.
+        let schematic = root
+            .get("Schematic")
+            .and_then(Tag::as_compound)
+            .unwrap_or(&root);
+
+        let version = get_int(schematic, "Version").unwrap_or(1);
+
+        // Sizes are unsigned shorts.
+        let dimension = |name| {
+            get_int(schematic, name)
+                .map(|size| size as u16 as i32)
+                .ok_or_else(|| invalid_data("schematic has no size"))
+        };
+
+        let size = IVec3::new(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+
+        let offset = match schematic.get("Offset").and_then(Tag::as_int_array) {
+            Some(&[x, y, z]) => IVec3::new(x, y, z),
+            _ => IVec3::ZERO,
+        };
+
+        let (palette, data) = if version >= 3 {
+            let blocks = schematic
+                .get("Blocks")
+                .and_then(Tag::as_compound)
+                .ok_or_else(|| invalid_data("schematic has no blocks"))?;
+
+            (blocks.get("Palette"), blocks.get("Data"))
+        } else {
+            (schematic.get("Palette"), schematic.get("BlockData"))
+        };
+
+        let palette = palette
+            .and_then(Tag::as_compound)
+            .ok_or_else(|| invalid_data("schematic has no palette"))?;
+
+        let mut states = Vec::new();
+
+        for (state, index) in palette {
+            let index = index
+                .as_i64()
+                .and_then(|index| usize::try_from(index).ok())
+                .filter(|&index| index < palette.len())
+                .ok_or_else(|| invalid_data("palette index is out of range"))?;
+
+            if states.len() <= index {
+                states.resize(index + 1, String::from("minecraft:air"));
+            }
+
+            states[index] = state.clone();
+        }
+
+        let mut schematic = Self {
+            size,
+            offset,
+            palette: states,
+            blocks: Vec::new(),
+        };
+
+        let data = data.and_then(Tag::as_byte_array).unwrap_or_default();
+
+        schematic.blocks = read_varints(data, schematic.volume())?;
+
+        Ok(schematic)
+    }
+
+    /// Writes a gzipped schematic.
+    pub fn write(&self, version: SchematicVersion) -> io::Result<Vec<u8>> {
+        if self.size.cmpgt(IVec3::splat(u16::MAX as i32)).any() {
+            return Err(invalid_data("schematic is too large"));
+        }
+
+        let palette = self
+            .palette
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (state.clone(), Tag::Int(index as i32)))
+            .collect();
+
+        let data = Tag::ByteArray(write_varints(&self.blocks));
+
+        let mut schematic = Compound::new();
+
+        schematic.insert("DataVersion".into(), Tag::Int(DATA_VERSION));
+        schematic.insert("Width".into(), Tag::Short(self.size.x as u16 as i16));
+        schematic.insert("Height".into(), Tag::Short(self.size.y as u16 as i16));
+        schematic.insert("Length".into(), Tag::Short(self.size.z as u16 as i16));
+        schematic.insert(
+            "Offset".into(),
+            Tag::IntArray(self.offset.to_array().to_vec()),
+        );
+
+        let bytes = match version {
+            SchematicVersion::V2 => {
+                schematic.insert("Version".into(), Tag::Int(2));
+                schematic.insert("PaletteMax".into(), Tag::Int(self.palette.len() as i32));
+                schematic.insert("Palette".into(), Tag::Compound(palette));
+                schematic.insert("BlockData".into(), data);
+
+                nbt::write("Schematic", &schematic)
+            }
+            SchematicVersion::V3 => {
+                let blocks = Compound::from([
+                    ("Palette".into(), Tag::Compound(palette)),
+                    ("Data".into(), data),
+                ]);
+
+                schematic.insert("Version".into(), Tag::Int(3));
+                schematic.insert("Blocks".into(), Tag::Compound(blocks));
+
+                nbt::write(
+                    "",
+                    &Compound::from([("Schematic".into(), Tag::Compound(schematic))]),
+                )
+            }
+        };
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        encoder.write_all(&bytes)?;
+        encoder.finish()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path, version: SchematicVersion) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, self.write(version)?)
+    }
+}
+
+/// Marks the corners of the region to export, and rotates pastes.
+fn select_region(
+    mut region: ResMut<SchematicSelection>,
+    action_state: Single<&ActionState<Action>, With<PlayerBody>>,
+    target: Res<TargetBlock>,
+) {
+    let target = target.map(|hit| hit.block);
+
+    if action_state.just_pressed(&Action::SelectFrom) {
+        if let Some(target) = target {
+            info!("selected from {target}");
+            region.from = Some(target);
+        }
+    }
+
+    if action_state.just_pressed(&Action::SelectTo) {
+        if let Some(target) = target {
+            info!("selected to {target}");
+            region.to = Some(target);
+        }
+    }
+
+    if action_state.just_pressed(&Action::Rotate) {
+        region.quarter_turns = (region.quarter_turns + 1) % 4;
+        info!(
+            "pasting rotated by {} degrees",
+            region.quarter_turns as u32 * 90
+        );
+    }
+}
+
+fn export_selection(
+    region: Res<SchematicSelection>,
+    settings: Res<SchematicSettings>,
+    chunk_map: Res<ChunkMap>,
+    registry: Res<BlockRegistry>,
+    action_state: Single<&ActionState<Action>, With<PlayerBody>>,
+) {
+    if !action_state.just_pressed(&Action::Export) {
+        return;
+    }
+
+    let (Some(from), Some(to)) = (region.from, region.to) else {
+        warn!("select both corners of a region before exporting it");
+        return;
+    };
+
+    let schematic = Schematic::copy(&chunk_map, &registry, from, to);
+
+    match schematic.save(&settings.path, settings.version) {
+        Ok(()) => info!(
+            "exported {} blocks to {}",
+            schematic.volume(),
+            settings.path.display()
+        ),
+        Err(error) => error!("failed to export {}: {error}", settings.path.display()),
+    }
+}
+
+/// Pastes the exported schematic with its origin on top of the targeted block.
+fn paste_schematic(
+    region: Res<SchematicSelection>,
+    settings: Res<SchematicSettings>,
+    mut chunk_map: ResMut<ChunkMap>,
+    registry: Res<BlockRegistry>,
+    action_state: Single<&ActionState<Action>, With<PlayerBody>>,
+    target: Res<TargetBlock>,
+) {
+    if !action_state.just_pressed(&Action::Paste) {
+        return;
+    }
+
+    let Some(hit) = **target else {
+        return;
+    };
+
+    let schematic = match Schematic::load(&settings.path) {
+        Ok(schematic) => schematic,
+        Err(error) => {
+            error!("failed to load {}: {error}", settings.path.display());
+            return;
+        }
+    };
+
+    let summary = schematic.paste(
+        &mut chunk_map,
+        &registry,
+        hit.block + IVec3::Y,
+        region.quarter_turns,
+    );
+
+    info!("{summary}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::tests::registry;
+    use crate::block::PLACEHOLDER_BLOCK;
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 127, 128, 300, 16384, u32::MAX >> 4];
+
+        assert_eq!(
+            read_varints(&write_varints(&values), values.len()).unwrap(),
+            values
+        );
+    }
+
+    #[test]
+    fn oversized_block_data_is_rejected() {
+        let count = 65535 * 65535 * 65535;
+
+        assert!(read_varints(&[0; 16], count).is_err());
+    }
+
+    /// A map with only the chunk at (1, 0, 1) loaded, covering 16 to 31 on X and Z.
+    fn loaded_chunk() -> ChunkMap {
+        let mut chunk_map = ChunkMap::default();
+
+        chunk_map.insert_chunk(IVec3::new(1, 0, 1), Default::default());
+        chunk_map
+    }
+
+    #[test]
+    fn copies_round_trip_through_files_and_rotations() {
+        let registry = registry(&["stone", "dirt", "log"]);
+        let [stone, dirt, log] =
+            ["stone", "dirt", "log"].map(|name| registry.by_name(name).unwrap());
+        let from = IVec3::new(2, 0, 3);
+
+        // An L of dirt along X, a log along Z and stone on top, so every turn looks different.
+        let structure = [
+            (IVec3::new(0, 0, 0), stone),
+            (IVec3::new(1, 0, 0), dirt),
+            (IVec3::new(2, 0, 0), dirt),
+            (IVec3::new(0, 0, 1), log),
+            (IVec3::new(0, 1, 0), stone),
+        ];
+
+        let mut chunk_map = ChunkMap::default();
+
+        for (offset, block) in structure {
+            chunk_map.set_block(from + offset, block);
+        }
+
+        let copied = Schematic::copy(&chunk_map, &registry, from, from + IVec3::new(2, 1, 1));
+
+        assert_eq!(copied.size, IVec3::new(3, 2, 2));
+
+        // Where the end of the dirt lands after each turn, clockwise seen from above.
+        let dirt_ends = [
+            IVec3::new(2, 0, 0),
+            IVec3::new(0, 0, 2),
+            IVec3::new(-2, 0, 0),
+            IVec3::new(0, 0, -2),
+        ];
+
+        for version in [SchematicVersion::V2, SchematicVersion::V3] {
+            let read = Schematic::read(&copied.write(version).unwrap()).unwrap();
+
+            assert_eq!(read.size, copied.size);
+            assert_eq!(read.offset, copied.offset);
+
+            for (quarter_turns, dirt_end) in dirt_ends.into_iter().enumerate() {
+                let position = IVec3::new(24, 4, 24);
+                let mut pasted = loaded_chunk();
+                let summary = read.paste(&mut pasted, &registry, position, quarter_turns as u8);
+
+                assert_eq!(summary.placed, 12, "{version:?} {quarter_turns}");
+                assert!(summary.unknown.is_empty());
+                assert_eq!(pasted.get_block(position + dirt_end), dirt);
+
+                for (offset, block) in structure {
+                    let target = position + rotate(offset, quarter_turns as u8);
+
+                    assert_eq!(
+                        pasted.get_block(target),
+                        block,
+                        "{version:?} {quarter_turns}"
+                    );
+                }
+
+                let solid = pasted.chunk(IVec3::new(1, 0, 1)).unwrap().iter().count();
+
+                assert_eq!(solid, structure.len());
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_blocks_become_the_placeholder() {
+        let registry = registry(&["stone", PLACEHOLDER_BLOCK]);
+        let schematic = Schematic {
+            size: IVec3::new(2, 1, 1),
+            offset: IVec3::ZERO,
+            palette: vec![
+                "minecraft:stone".to_string(),
+                "minecraft:mystery[facing=north]".to_string(),
+            ],
+            blocks: vec![0, 1],
+        };
+
+        let read = Schematic::read(&schematic.write(SchematicVersion::V3).unwrap()).unwrap();
+        let position = IVec3::new(20, 0, 20);
+        let mut chunk_map = loaded_chunk();
+        let summary = read.paste(&mut chunk_map, &registry, position, 0);
+
+        assert_eq!(
+            chunk_map.get_block(position),
+            registry.by_name("stone").unwrap()
+        );
+        assert_eq!(
+            chunk_map.get_block(position + IVec3::X),
+            registry.placeholder()
+        );
+        assert_eq!(
+            summary.unknown,
+            HashMap::from([("minecraft:mystery".to_string(), 1)])
+        );
+    }
+}
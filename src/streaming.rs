@@ -0,0 +1,322 @@
+use crate::block::BlockRegistry;
+use crate::material::BlockMaterial;
+use crate::mesh::{self, MeshingMode};
+use crate::physics::{apply_gravity, move_and_slide};
+use crate::save::WorldSave;
+use crate::world::{chunk_origin, split_position, Chunk, ChunkEntities, ChunkMap, ChunkPosition};
+use crate::worldgen::WorldGen;
+use crate::PlayerBody;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Loads chunks around the player and unloads far away ones, generating and
+/// meshing them in the background.
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderDistance>()
+            .init_resource::<ChunkEntities>()
+            .init_resource::<ChunkTasks>()
+            .add_systems(
+                Update,
+                (
+                    update_loaded_chunks,
+                    finish_generating,
+                    start_meshing,
+                    finish_meshing,
+                )
+                    .chain()
+                    .run_if(resource_exists::<WorldGen>),
+            )
+            .add_systems(
+                FixedUpdate,
+                hold_player
+                    .after(apply_gravity)
+                    .before(move_and_slide)
+                    .run_if(resource_exists::<WorldGen>),
+            );
+    }
+}
+
+/// How far chunks are loaded around the player, in chunks.
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct RenderDistance {
+    pub horizontal: i32,
+    pub vertical: i32,
+}
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self {
+            horizontal: 8,
+            vertical: 4,
+        }
+    }
+}
+
+impl RenderDistance {
+    fn contains(&self, center: IVec3, chunk_position: IVec3) -> bool {
+        let offset = (chunk_position - center).abs();
+
+        offset.x <= self.horizontal && offset.z <= self.horizontal && offset.y <= self.vertical
+    }
+}
+
+/// The most chunks generated at once.
+const MAX_GENERATING: usize = 16;
+
+/// The most chunks meshed at once.
+const MAX_MESHING: usize = 16;
+
+/// The material used by every chunk mesh.
+#[derive(Resource)]
+pub struct ChunkMaterial(pub Handle<BlockMaterial>);
+
+/// The block registry shared with meshing tasks, built once rather than per task.
+#[derive(Clone, Deref, Resource)]
+pub struct MeshingRegistry(pub Arc<BlockRegistry>);
+
+#[derive(Default, Resource)]
+struct ChunkTasks {
+    generating: HashMap<IVec3, Task<Chunk>>,
+    meshing: HashMap<IVec3, Task<Option<(Mesh, Collider)>>>,
+    /// Chunks waiting to be meshed.
+    remesh: HashSet<IVec3>,
+    /// Chunks that have been meshed at least once since being loaded.
+    meshed: HashSet<IVec3>,
+}
+
+fn player_chunk(transform: &Transform) -> IVec3 {
+    split_position(transform.translation.floor().as_ivec3()).0
+}
+
+/// Orders chunk positions from nearest to furthest from `center`.
+fn sort_by_distance(positions: &mut [IVec3], center: IVec3) {
+    positions.sort_by_key(|position| (*position - center).length_squared());
+}
+
+/// Unloads chunks outside of the render distance, leaving modified ones for the next autosave,
+/// and starts loading or generating the nearest missing ones.
+fn update_loaded_chunks(
+    mut commands: Commands,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    mut tasks: ResMut<ChunkTasks>,
+    player: Single<&Transform, With<PlayerBody>>,
+    render_distance: Res<RenderDistance>,
+    generator: Res<WorldGen>,
+    save: Res<WorldSave>,
+) {
+    let center = player_chunk(&player);
+
+    let far: Vec<_> = chunk_map
+        .chunk_positions()
+        .filter(|&position| !render_distance.contains(center, position))
+        .collect();
+
+    for chunk_position in far {
+        chunk_map.unload_chunk(chunk_position);
+
+        tasks.meshing.remove(&chunk_position);
+        tasks.remesh.remove(&chunk_position);
+        tasks.meshed.remove(&chunk_position);
+
+        if let Some(entity) = chunk_entities.remove(&chunk_position) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    tasks
+        .generating
+        .retain(|&position, _| render_distance.contains(center, position));
+
+    if tasks.generating.len() >= MAX_GENERATING {
+        return;
+    }
+
+    let RenderDistance {
+        horizontal,
+        vertical,
+    } = *render_distance;
+
+    let mut missing = Vec::new();
+
+    for x in -horizontal..=horizontal {
+        for y in -vertical..=vertical {
+            for z in -horizontal..=horizontal {
+                let chunk_position = center + IVec3::new(x, y, z);
+
+                if chunk_map.contains_chunk(chunk_position)
+                    || tasks.generating.contains_key(&chunk_position)
+                    || chunk_map.reload_chunk(chunk_position)
+                {
+                    continue;
+                }
+
+                missing.push(chunk_position);
+            }
+        }
+    }
+
+    sort_by_distance(&mut missing, center);
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let free = MAX_GENERATING - tasks.generating.len();
+
+    for chunk_position in missing.into_iter().take(free) {
+        let generator = generator.0.clone();
+        let save = save.clone();
+
+        let task = task_pool.spawn(async move {
+            match save.load_chunk(chunk_position) {
+                Ok(Some(chunk)) => return chunk,
+                Ok(None) => {}
+                Err(error) => error!("failed to load chunk {chunk_position}: {error}"),
+            }
+
+            generator.generate(chunk_position)
+        });
+
+        tasks.generating.insert(chunk_position, task);
+    }
+}
+
+fn finish_generating(mut chunk_map: ResMut<ChunkMap>, mut tasks: ResMut<ChunkTasks>) {
+    tasks.generating.retain(|&chunk_position, task| {
+        let Some(chunk) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+
+        chunk_map.insert_chunk(chunk_position, chunk);
+
+        false
+    });
+}
+
+/// Starts meshing the nearest modified chunks.
+fn start_meshing(
+    mut commands: Commands,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    mut tasks: ResMut<ChunkTasks>,
+    player: Single<&Transform, With<PlayerBody>>,
+    meshing_mode: Res<MeshingMode>,
+    registry: Res<MeshingRegistry>,
+) {
+    if meshing_mode.is_changed() {
+        chunk_map.mark_all_dirty();
+    }
+
+    let tasks = &mut *tasks;
+
+    for chunk_position in chunk_map.drain_dirty() {
+        // A newer mesh is needed, so any mesh in progress is outdated.
+        tasks.meshing.remove(&chunk_position);
+        tasks.remesh.insert(chunk_position);
+    }
+
+    if tasks.meshing.len() >= MAX_MESHING || tasks.remesh.is_empty() {
+        return;
+    }
+
+    let mut remesh: Vec<_> = tasks.remesh.iter().copied().collect();
+
+    sort_by_distance(&mut remesh, player_chunk(&player));
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let free = MAX_MESHING - tasks.meshing.len();
+
+    for chunk_position in remesh.into_iter().take(free) {
+        tasks.remesh.remove(&chunk_position);
+
+        if !chunk_map.contains_chunk(chunk_position) {
+            if let Some(entity) = chunk_entities.remove(&chunk_position) {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            continue;
+        }
+
+        let neighbourhood = chunk_map.neighbourhood(chunk_position);
+        let registry = Arc::clone(&registry);
+        let meshing_mode = *meshing_mode;
+
+        let task = task_pool.spawn(async move {
+            let mesh = mesh::new_chunk(&neighbourhood, chunk_position, &*registry, meshing_mode)?;
+            let collider = Collider::trimesh_from_mesh(&mesh)?;
+
+            Some((mesh, collider))
+        });
+
+        tasks.meshing.insert(chunk_position, task);
+    }
+}
+
+/// Replaces the mesh and collider of chunks that finished meshing.
+fn finish_meshing(
+    mut commands: Commands,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut tasks: ResMut<ChunkTasks>,
+    material: Res<ChunkMaterial>,
+) {
+    let ChunkTasks {
+        meshing, meshed, ..
+    } = &mut *tasks;
+
+    meshing.retain(|&chunk_position, task| {
+        let Some(result) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+
+        meshed.insert(chunk_position);
+
+        let Some((mesh, collider)) = result else {
+            if let Some(entity) = chunk_entities.remove(&chunk_position) {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            return false;
+        };
+
+        let components = (
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(material.0.clone()),
+            collider,
+        );
+
+        if let Some(&entity) = chunk_entities.get(&chunk_position) {
+            commands.entity(entity).insert(components);
+        } else {
+            let entity = commands
+                .spawn((
+                    ChunkPosition(chunk_position),
+                    Transform::from_translation(chunk_origin(chunk_position).as_vec3()),
+                    RigidBody::Static,
+                    components,
+                ))
+                .id();
+
+            chunk_entities.insert(chunk_position, entity);
+        }
+
+        false
+    });
+}
+
+/// Keeps the player from falling into the void while the chunks around them are still loading.
+fn hold_player(
+    tasks: Res<ChunkTasks>,
+    player: Single<(&Transform, &mut LinearVelocity), With<PlayerBody>>,
+) {
+    let (transform, mut linear_velocity) = player.into_inner();
+    let center = player_chunk(transform);
+
+    if !tasks.meshed.contains(&center) || !tasks.meshed.contains(&(center - IVec3::Y)) {
+        linear_velocity.0 = Vec3::ZERO;
+    }
+}
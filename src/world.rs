@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// The length of a chunk along each axis, in blocks.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// The number of blocks in a single chunk.
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Identifies a type of block, `BlockId::AIR` is the empty block.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    pub const AIR: Self = Self(0);
+
+    pub const fn is_air(self) -> bool {
+        self.0 == Self::AIR.0
+    }
+}
+
+/// A cube of `CHUNK_SIZE`³ blocks.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    blocks: Box<[BlockId]>,
+    solid: usize,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self {
+            blocks: vec![BlockId::AIR; CHUNK_VOLUME].into_boxed_slice(),
+            solid: 0,
+        }
+    }
+}
+
+impl Chunk {
+    fn index(local: IVec3) -> usize {
+        debug_assert!(
+            local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNK_SIZE)).all()
+        );
+
+        (local.x + local.z * CHUNK_SIZE + local.y * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+
+    /// Returns the block at a position local to this chunk.
+    pub fn get(&self, local: IVec3) -> BlockId {
+        self.blocks[Self::index(local)]
+    }
+
+    /// Replaces the block at a position local to this chunk, returning the previous block.
+    pub fn set(&mut self, local: IVec3, block: BlockId) -> BlockId {
+        let previous = std::mem::replace(&mut self.blocks[Self::index(local)], block);
+
+        match (previous.is_air(), block.is_air()) {
+            (true, false) => self.solid += 1,
+            (false, true) => self.solid -= 1,
+            _ => {}
+        }
+
+        previous
+    }
+
+    /// Builds a chunk from its blocks, in the same order as [`Chunk::blocks`].
+    pub fn from_blocks(blocks: Vec<BlockId>) -> Option<Self> {
+        if blocks.len() != CHUNK_VOLUME {
+            return None;
+        }
+
+        let solid = blocks.iter().filter(|block| !block.is_air()).count();
+
+        Some(Self {
+            blocks: blocks.into_boxed_slice(),
+            solid,
+        })
+    }
+
+    /// Every block in this chunk, ordered by Y, then Z, then X.
+    pub fn blocks(&self) -> &[BlockId] {
+        &self.blocks
+    }
+
+    /// Whether every block in this chunk is air.
+    pub fn is_empty(&self) -> bool {
+        self.solid == 0
+    }
+
+    /// Iterates over every non-air block along with its local position.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, BlockId)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| !block.is_air())
+            .map(|(index, block)| {
+                let index = index as i32;
+                let local = IVec3::new(
+                    index % CHUNK_SIZE,
+                    index / (CHUNK_SIZE * CHUNK_SIZE),
+                    index / CHUNK_SIZE % CHUNK_SIZE,
+                );
+
+                (local, *block)
+            })
+    }
+}
+
+/// Splits a block position into the position of its chunk, and its position within that chunk.
+pub fn split_position(position: IVec3) -> (IVec3, IVec3) {
+    let size = IVec3::splat(CHUNK_SIZE);
+
+    (position.div_euclid(size), position.rem_euclid(size))
+}
+
+/// The block position of a chunk's first block.
+pub fn chunk_origin(chunk_position: IVec3) -> IVec3 {
+    chunk_position * CHUNK_SIZE
+}
+
+/// Every block in the world, stored in chunks.
+#[derive(Debug, Default, Resource)]
+pub struct ChunkMap {
+    chunks: HashMap<IVec3, Chunk>,
+    dirty: HashSet<IVec3>,
+    /// Chunks modified since they were last saved.
+    unsaved: HashSet<IVec3>,
+    /// Modified chunks that were unloaded, kept until the next save.
+    unloaded: HashMap<IVec3, Chunk>,
+}
+
+impl ChunkMap {
+    /// Returns the block at `position`, unloaded chunks are treated as air.
+    pub fn get_block(&self, position: IVec3) -> BlockId {
+        let (chunk_position, local) = split_position(position);
+
+        self.chunks
+            .get(&chunk_position)
+            .map_or(BlockId::AIR, |chunk| chunk.get(local))
+    }
+
+    /// Replaces the block at `position`, returning the previous block.
+    pub fn set_block(&mut self, position: IVec3, block: BlockId) -> BlockId {
+        let (chunk_position, local) = split_position(position);
+        let previous = self
+            .chunks
+            .entry(chunk_position)
+            .or_default()
+            .set(local, block);
+
+        if previous != block {
+            self.dirty.insert(chunk_position);
+            self.unsaved.insert(chunk_position);
+
+            // Faces in neighbouring chunks may have been covered or exposed.
+            for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+                if local.dot(axis) == 0 {
+                    self.dirty.insert(chunk_position - axis);
+                } else if local.dot(axis) == CHUNK_SIZE - 1 {
+                    self.dirty.insert(chunk_position + axis);
+                }
+            }
+        }
+
+        previous
+    }
+
+    /// Inserts a whole chunk, replacing any chunk already at `chunk_position`.
+    pub fn insert_chunk(&mut self, chunk_position: IVec3, chunk: Chunk) {
+        self.chunks.insert(chunk_position, chunk);
+        self.dirty.insert(chunk_position);
+
+        for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+            self.dirty.insert(chunk_position - axis);
+            self.dirty.insert(chunk_position + axis);
+        }
+    }
+
+    /// Unloads a chunk. Modified chunks are kept until the next save, so that unloading never
+    /// waits on the disk.
+    pub fn unload_chunk(&mut self, chunk_position: IVec3) {
+        let Some(chunk) = self.chunks.remove(&chunk_position) else {
+            return;
+        };
+
+        if self.unsaved.contains(&chunk_position) {
+            self.unloaded.insert(chunk_position, chunk);
+        }
+    }
+
+    /// Loads a chunk back in if it was unloaded before it could be saved, returning whether it
+    /// was.
+    pub fn reload_chunk(&mut self, chunk_position: IVec3) -> bool {
+        let Some(chunk) = self.unloaded.remove(&chunk_position) else {
+            return false;
+        };
+
+        self.insert_chunk(chunk_position, chunk);
+
+        true
+    }
+
+    /// A chunk that is either loaded, or unloaded and waiting to be saved.
+    pub fn unsaved_chunk(&self, chunk_position: IVec3) -> Option<&Chunk> {
+        self.chunks
+            .get(&chunk_position)
+            .or_else(|| self.unloaded.get(&chunk_position))
+    }
+
+    /// Drops the unloaded chunks, once they have been saved.
+    pub fn clear_unloaded(&mut self) {
+        self.unloaded.clear();
+    }
+
+    /// Whether a chunk was modified since it was last saved.
+    pub fn is_unsaved(&self, chunk_position: IVec3) -> bool {
+        self.unsaved.contains(&chunk_position)
+    }
+
+    /// Takes the positions of every chunk modified since it was last saved.
+    pub fn take_unsaved(&mut self) -> Vec<IVec3> {
+        self.unsaved.drain().collect()
+    }
+
+    /// Marks chunks as modified again, after saving them failed.
+    pub fn restore_unsaved(&mut self, chunk_positions: impl IntoIterator<Item = IVec3>) {
+        self.unsaved.extend(chunk_positions);
+    }
+
+    pub fn contains_chunk(&self, chunk_position: IVec3) -> bool {
+        self.chunks.contains_key(&chunk_position)
+    }
+
+    /// Copies a chunk along with its six neighbours, which is everything needed to mesh it.
+    pub fn neighbourhood(&self, chunk_position: IVec3) -> ChunkMap {
+        let mut neighbourhood = ChunkMap::default();
+        let offsets = [
+            IVec3::ZERO,
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ];
+
+        for offset in offsets {
+            let position = chunk_position + offset;
+
+            if let Some(chunk) = self.chunks.get(&position) {
+                neighbourhood.chunks.insert(position, chunk.clone());
+            }
+        }
+
+        neighbourhood
+    }
+
+    pub fn chunk(&self, chunk_position: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk_position)
+    }
+
+    /// Marks every chunk as modified, so that they are all remeshed.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.extend(self.chunks.keys());
+    }
+
+    /// Iterates over the position of every loaded chunk.
+    pub fn chunk_positions(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Takes the positions of every chunk modified since the last call.
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = IVec3> + '_ {
+        self.dirty.drain()
+    }
+}
+
+/// A marker component for the entity holding a chunk's mesh and collider.
+#[derive(Component, Debug)]
+pub struct ChunkPosition(pub IVec3);
+
+/// Maps chunk positions to their entities.
+#[derive(Debug, Default, Deref, DerefMut, Resource)]
+pub struct ChunkEntities(HashMap<IVec3, Entity>);
+
+/// How long the world has existed, in seconds of world time, which passes at the
+/// [`crate::daylight::TimeRate`].
+#[derive(Debug, Default, Resource)]
+pub struct WorldTime(pub f64);
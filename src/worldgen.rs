@@ -0,0 +1,316 @@
+use crate::block::BlockRegistry;
+use crate::world::{chunk_origin, BlockId, Chunk, CHUNK_SIZE};
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Fills chunks with blocks, the same chunk position must always produce the same chunk.
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, chunk_position: IVec3) -> Chunk;
+}
+
+/// The generator used for new chunks.
+#[derive(Clone, Deref, Resource)]
+pub struct WorldGen(pub Arc<dyn WorldGenerator>);
+
+/// Which generator a world uses.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum GeneratorKind {
+    #[default]
+    Noise,
+    Superflat,
+}
+
+/// The blocks terrain is built from.
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainBlocks {
+    pub grass: BlockId,
+    pub dirt: BlockId,
+    pub stone: BlockId,
+}
+
+impl TerrainBlocks {
+    /// Looks up the terrain blocks by name, falling back to air for missing blocks.
+    pub fn from_registry(registry: &BlockRegistry) -> Self {
+        let block = |name| {
+            registry.by_name(name).unwrap_or_else(|| {
+                warn!("terrain block {name} is not registered");
+                BlockId::AIR
+            })
+        };
+
+        Self {
+            grass: block("grass"),
+            dirt: block("dirt"),
+            stone: block("stone"),
+        }
+    }
+}
+
+/// Rolling hills from a noise heightmap, with overhangs carved by 3D noise.
+pub struct NoiseGenerator {
+    blocks: TerrainBlocks,
+    height: Fbm<Perlin>,
+    density: Fbm<Perlin>,
+}
+
+impl NoiseGenerator {
+    /// The height terrain is centered around.
+    const SEA_LEVEL: f64 = 0.0;
+    /// How far the heightmap strays from `SEA_LEVEL`.
+    const HEIGHT_SCALE: f64 = 24.0;
+    /// How far 3D density can move the surface up or down.
+    const DENSITY_SCALE: f64 = 8.0;
+    /// The depth of dirt below the surface.
+    const DIRT_DEPTH: i32 = 3;
+
+    pub fn new(seed: u64, blocks: TerrainBlocks) -> Self {
+        let height = Fbm::<Perlin>::new(layer_seed(seed, 0))
+            .set_octaves(5)
+            .set_frequency(1.0 / 256.0)
+            .set_persistence(0.5);
+
+        let density = Fbm::<Perlin>::new(layer_seed(seed, 1))
+            .set_octaves(3)
+            .set_frequency(1.0 / 48.0)
+            .set_persistence(0.5);
+
+        Self {
+            blocks,
+            height,
+            density,
+        }
+    }
+
+    fn surface_height(&self, x: i32, z: i32) -> f64 {
+        Self::SEA_LEVEL + self.height.get([x as f64, z as f64]) * Self::HEIGHT_SCALE
+    }
+
+    fn is_solid(&self, surface_height: f64, position: IVec3) -> bool {
+        let density = self.density.get(position.as_dvec3().to_array()) * Self::DENSITY_SCALE;
+
+        surface_height - position.y as f64 + density > 0.0
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, chunk_position: IVec3) -> Chunk {
+        let origin = chunk_origin(chunk_position);
+        let mut chunk = Chunk::default();
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let surface_height = self.surface_height(origin.x + x, origin.z + z);
+
+                // Counts solid blocks from the top of the column down, starting above the chunk
+                // so that the first blocks of a chunk know whether they're at the surface.
+                let mut depth = (1..=Self::DIRT_DEPTH + 1)
+                    .take_while(|above| {
+                        let position = origin + IVec3::new(x, CHUNK_SIZE - 1 + above, z);
+
+                        self.is_solid(surface_height, position)
+                    })
+                    .count() as i32;
+
+                for y in (0..CHUNK_SIZE).rev() {
+                    let local = IVec3::new(x, y, z);
+
+                    if !self.is_solid(surface_height, origin + local) {
+                        depth = 0;
+                        continue;
+                    }
+
+                    let block = match depth {
+                        0 => self.blocks.grass,
+                        depth if depth <= Self::DIRT_DEPTH => self.blocks.dirt,
+                        _ => self.blocks.stone,
+                    };
+
+                    chunk.set(local, block);
+                    depth += 1;
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+/// Flat layers of blocks, for building.
+pub struct SuperflatGenerator {
+    /// The height of the topmost layer.
+    top: i32,
+    /// Layers from the top down, everything below the last layer is empty.
+    layers: Vec<BlockId>,
+}
+
+impl SuperflatGenerator {
+    pub fn new(top: i32, layers: Vec<BlockId>) -> Self {
+        Self { top, layers }
+    }
+
+    /// A layer of grass on two layers of dirt, on top of stone.
+    pub fn from_blocks(top: i32, blocks: TerrainBlocks) -> Self {
+        Self::new(
+            top,
+            vec![
+                blocks.grass,
+                blocks.dirt,
+                blocks.dirt,
+                blocks.stone,
+                blocks.stone,
+                blocks.stone,
+            ],
+        )
+    }
+}
+
+impl WorldGenerator for SuperflatGenerator {
+    fn generate(&self, chunk_position: IVec3) -> Chunk {
+        let origin = chunk_origin(chunk_position);
+        let mut chunk = Chunk::default();
+
+        for y in 0..CHUNK_SIZE {
+            let Some(&block) = usize::try_from(self.top - (origin.y + y))
+                .ok()
+                .and_then(|layer| self.layers.get(layer))
+            else {
+                continue;
+            };
+
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set(IVec3::new(x, y, z), block);
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+/// Derives the seed of a noise layer from the world seed.
+fn layer_seed(seed: u64, layer: u64) -> u32 {
+    // SplitMix64, so that nearby world seeds give unrelated layers.
+    let mut z = seed.wrapping_add(layer.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    (z ^ (z >> 31)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCKS: TerrainBlocks = TerrainBlocks {
+        grass: BlockId(1),
+        dirt: BlockId(2),
+        stone: BlockId(3),
+    };
+
+    /// Chunks around the surface, where the terrain varies the most.
+    fn chunk_positions() -> impl Iterator<Item = IVec3> {
+        (-2..2).flat_map(|y| (-2..2).map(move |x| IVec3::new(x, y, 3)))
+    }
+
+    #[test]
+    fn same_seed_generates_same_chunks() {
+        let a = NoiseGenerator::new(42, BLOCKS);
+        let b = NoiseGenerator::new(42, BLOCKS);
+
+        for position in chunk_positions() {
+            assert_eq!(
+                a.generate(position).blocks(),
+                b.generate(position).blocks(),
+                "chunk {position} differs"
+            );
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_chunks() {
+        let a = NoiseGenerator::new(42, BLOCKS);
+        let b = NoiseGenerator::new(43, BLOCKS);
+
+        assert!(chunk_positions()
+            .any(|position| { a.generate(position).blocks() != b.generate(position).blocks() }));
+    }
+
+    #[test]
+    fn superflat_generates_layers() {
+        let generator = SuperflatGenerator::from_blocks(-10, BLOCKS);
+        let chunk = generator.generate(IVec3::NEG_Y);
+        let origin = chunk_origin(IVec3::NEG_Y);
+
+        for y in 0..CHUNK_SIZE {
+            let expected = match origin.y + y {
+                -10 => BLOCKS.grass,
+                -12..=-11 => BLOCKS.dirt,
+                -15..=-13 => BLOCKS.stone,
+                _ => BlockId::AIR,
+            };
+
+            for (x, z) in [(0, 0), (7, 3), (15, 15)] {
+                assert_eq!(chunk.get(IVec3::new(x, y, z)), expected, "at height {y}");
+            }
+        }
+
+        assert!(generator.generate(IVec3::ZERO).is_empty());
+        assert!(generator.generate(IVec3::new(0, -2, 0)).is_empty());
+    }
+
+    /// FNV-1a over the block ids of a chunk, which unlike `DefaultHasher` never changes.
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        chunk
+            .blocks()
+            .iter()
+            .flat_map(|block| block.0.to_le_bytes())
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn noise_matches_golden_chunks() {
+        let generator = NoiseGenerator::new(42, BLOCKS);
+
+        // Changing these means existing worlds generate differently from before.
+        assert_eq!(
+            chunk_hash(&generator.generate(IVec3::ZERO)),
+            0x2c7528ec7fbfbeb6
+        );
+        assert_eq!(
+            chunk_hash(&generator.generate(IVec3::NEG_Y)),
+            0xa8a8759dc23af19e
+        );
+        assert_eq!(
+            chunk_hash(&generator.generate(IVec3::new(1, 0, -1))),
+            0xdf281e6f9ff705b7
+        );
+    }
+
+    #[test]
+    fn grass_sits_on_dirt_on_stone() {
+        let generator = NoiseGenerator::new(42, BLOCKS);
+        let chunk = generator.generate(IVec3::NEG_Y);
+
+        // The surface of this column is at the top of the chunk.
+        for y in 0..CHUNK_SIZE {
+            let expected = match y {
+                15 => BLOCKS.grass,
+                12..=14 => BLOCKS.dirt,
+                _ => BLOCKS.stone,
+            };
+
+            assert_eq!(chunk.get(IVec3::new(0, y, 0)), expected, "at height {y}");
+        }
+
+        assert_eq!(
+            generator.generate(IVec3::ZERO).get(IVec3::ZERO),
+            BlockId::AIR
+        );
+    }
+}